@@ -15,7 +15,7 @@ fn stabilize_linear_nodes_simple(c: &mut Criterion) {
                 ),
                 &(*node_count, *observed),
                 |b, (node_count, observed)| {
-                    let mut engine = Engine::new_with_max_height(1003);
+                    let mut engine = Engine::new();
                     let first_num_var = Variable::new(0u64);
                     let mut node = first_num_var.watch();
                     for _ in 0..*node_count {
@@ -51,7 +51,7 @@ fn stabilize_linear_nodes_cutoff(c: &mut Criterion) {
                 ),
                 &(*node_count, *observed),
                 |b, (node_count, observed)| {
-                    let mut engine = Engine::new_with_max_height(1003);
+                    let mut engine = Engine::new();
                     let first_num_var = Variable::new(0u64);
                     let node = first_num_var
                         .watch()
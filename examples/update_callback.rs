@@ -7,15 +7,10 @@ fn main() {
     let fish_count = Variable::new(1);
     let total_mammals = (&cat_count.watch(), &dog_count.watch()).map(|cats, dogs| cats + dogs);
     let total_animals = (&total_mammals, &fish_count.watch()).map(|mammals, fish| mammals + fish);
-    let mammal_callback =
-        total_mammals.map(|total_mammals| println!("mammals updated: {:?}", total_mammals));
-    let animal_callback =
-        total_animals.map(|total_animals| println!("animals updated: {:?}", total_animals));
-    engine.mark_observed(&mammal_callback);
-    engine.mark_observed(&animal_callback);
-
-    println!("stabilizing...");
-    engine.stabilize();
+    let mammal_observer = engine.observe(&total_mammals);
+    mammal_observer.on_update(|old, new| println!("mammals updated: {:?} -> {:?}", old, new));
+    let animal_observer = engine.observe(&total_animals);
+    animal_observer.on_update(|old, new| println!("animals updated: {:?} -> {:?}", old, new));
 
     cat_count.set(2);
     dog_count.set(2);
@@ -1,6 +1,11 @@
-use std::marker::PhantomData;
+use std::{
+    future::Future,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    panic::Location,
+};
 
-use crate::core::{AnchorHandle, Engine};
+use crate::core::{AnchorHandle, ConstAnchor, Constant, Engine, FromFuture, Mount};
 
 mod multi;
 
@@ -26,6 +31,34 @@ impl<O, E: Engine> Anchor<O, E> {
             phantom: PhantomData,
         }
     }
+
+    pub(crate) fn handle(&self) -> &E::AnchorHandle {
+        &self.data
+    }
+
+    /// A constant value's anchor without a corresponding `Constant`.
+    #[track_caller]
+    pub fn constant(value: O) -> Self
+    where
+        O: 'static,
+        E: Mount<ConstAnchor<O>>,
+    {
+        Constant::new(value).into_anchor()
+    }
+
+    /// Creates an Anchor that outputs the value `future` resolves to.
+    ///
+    /// Anchors depending on it stay pending until then; `Engine::get` blocks until it resolved,
+    /// while `Engine::get_async` yields to the surrounding executor.
+    #[track_caller]
+    pub fn from_future<F>(future: F) -> Self
+    where
+        F: Future<Output = O>,
+        O: 'static,
+        E: Mount<FromFuture<F>>,
+    {
+        E::mount(FromFuture::new(future, Location::caller()))
+    }
 }
 
 impl<O, E: Engine> Clone for Anchor<O, E> {
@@ -44,3 +77,9 @@ impl<O, E: Engine> PartialEq for Anchor<O, E> {
 }
 
 impl<O, E: Engine> Eq for Anchor<O, E> {}
+
+impl<O, E: Engine> Hash for Anchor<O, E> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
@@ -1,6 +1,9 @@
-use std::panic::Location;
+use std::{hash::Hash, panic::Location};
 
-use crate::core::{AnchorCore, Cutoff, Engine, Map, MapMut, RefMap, Then};
+use crate::core::{
+    AnchorCore, Cutoff, Engine, Freeze, Map, MapMut, Mount, Pausable, RefMap, ScopedEngine, Then,
+    ThenMemo,
+};
 
 use super::Anchor;
 
@@ -18,31 +21,36 @@ pub trait MultiAnchor<E: Engine>: Sized {
     where
         Out: 'static,
         F: 'static,
-        Map<Self::Target, F, Out>: AnchorCore<E, Output = Out>;
+        Map<Self::Target, F, Out>: AnchorCore<E, Output = Out>,
+        E: Mount<Map<Self::Target, F, Out>>;
 
     fn map_mut<F, Out>(self, initial: Out, f: F) -> Anchor<Out, E>
     where
         Out: 'static,
         F: 'static,
-        MapMut<Self::Target, F, Out>: AnchorCore<E, Output = Out>;
+        MapMut<Self::Target, F, Out>: AnchorCore<E, Output = Out>,
+        E: Mount<MapMut<Self::Target, F, Out>>;
 
     fn then<F, Out>(self, f: F) -> Anchor<Out, E>
     where
         F: 'static,
         Out: 'static,
-        Then<Self::Target, Out, F, E>: AnchorCore<E, Output = Out>;
+        Then<Self::Target, Out, F, E>: AnchorCore<E, Output = Out>,
+        E: Mount<Then<Self::Target, Out, F, E>>;
 
     fn cutoff<F, Out>(self, _f: F) -> Anchor<Out, E>
     where
         Out: 'static,
         F: 'static,
-        Cutoff<Self::Target, F>: AnchorCore<E, Output = Out>;
+        Cutoff<Self::Target, F>: AnchorCore<E, Output = Out>,
+        E: Mount<Cutoff<Self::Target, F>>;
 
     fn refmap<F, Out>(self, _f: F) -> Anchor<Out, E>
     where
         Out: 'static,
         F: 'static,
-        RefMap<Self::Target, F>: AnchorCore<E, Output = Out>;
+        RefMap<Self::Target, F>: AnchorCore<E, Output = Out>,
+        E: Mount<RefMap<Self::Target, F>>;
 }
 
 impl<O1, E> Anchor<O1, E>
@@ -77,6 +85,7 @@ where
         Out: 'static,
         F: 'static,
         Map<(Anchor<O1, E>,), F, Out>: AnchorCore<E, Output = Out>,
+        E: Mount<Map<(Anchor<O1, E>,), F, Out>>,
     {
         E::mount(Map::new((self.clone(),), f, Location::caller()))
     }
@@ -87,6 +96,7 @@ where
         Out: 'static,
         F: 'static,
         MapMut<(Anchor<O1, E>,), F, Out>: AnchorCore<E, Output = Out>,
+        E: Mount<MapMut<(Anchor<O1, E>,), F, Out>>,
     {
         E::mount(MapMut::new((self.clone(),), f, Location::caller(), initial))
     }
@@ -130,10 +140,101 @@ where
         F: 'static,
         Out: 'static,
         Then<(Anchor<O1, E>,), Out, F, E>: AnchorCore<E, Output = Out>,
+        E: Mount<Then<(Anchor<O1, E>,), Out, F, E>>,
     {
         E::mount(Then::new((self.clone(),), f, Location::caller()))
     }
 
+    /// Creates an anchor that maps a number of incremental input values to some output Anchor,
+    /// like `then`, but invalidates the Anchors `f` created once it's called again.
+    ///
+    /// Invalidated Anchors are dropped from the graph's calculations, and using them afterwards
+    /// is reported as `Error::Invalidated`, so branches built by earlier calls don't linger.
+    /// `f` mustn't return Anchors it created in an earlier call, e.g. from a cache; use `then`
+    /// or `then_memo` for that.
+    ///
+    /// Only engines implementing `ScopedEngine`, like the `single_threaded` one, support this.
+    /// To scope a bind over several Anchors, combine them with `MultiAnchor::map` first.
+    ///
+    /// ```
+    /// use anchors::single_threaded::*;
+    ///
+    /// let mut engine = Engine::new();
+    /// let offset = Variable::new(1);
+    /// let num = Anchor::constant(1);
+    /// let res = offset.watch().then_scoped(move |offset| {
+    ///     let offset = *offset;
+    ///     num.map(move |num| *num + offset)
+    /// });
+    /// assert_eq!(2, engine.get(&res));
+    ///
+    /// // the `map` built by the first call is invalidated
+    /// offset.set(2);
+    /// assert_eq!(3, engine.get(&res));
+    /// ```
+    #[track_caller]
+    pub fn then_scoped<F, Out>(&self, f: F) -> Anchor<Out, E>
+    where
+        F: 'static,
+        Out: 'static,
+        Then<(Anchor<O1, E>,), Out, F, E>: AnchorCore<E, Output = Out>,
+        E: ScopedEngine + Mount<Then<(Anchor<O1, E>,), Out, F, E>>,
+    {
+        E::mount(Then::new_scoped((self.clone(),), f, Location::caller()))
+    }
+
+    /// Creates an Anchor that maps this anchor's value to a key with `key_fn`, and outputs the
+    /// Anchor `build_fn` builds for that key, like `then` does.
+    ///
+    /// Unlike `then`, it remembers the Anchors it built for the `capacity` most recently used
+    /// keys, and keeps them alive. Switching back to a remembered key doesn't call `build_fn`
+    /// again, and doesn't recalculate the branch unless its inputs changed in the meantime.
+    ///
+    /// Panics if `capacity` is zero.
+    ///
+    /// ```
+    /// use anchors::single_threaded::*;
+    ///
+    /// let mut engine = Engine::new();
+    /// let tab = Variable::new("home");
+    /// let page = tab.watch().then_memo(
+    ///     |tab| *tab,
+    ///     |tab| Anchor::constant(format!("contents of {}", tab)),
+    ///     8,
+    /// );
+    /// assert_eq!("contents of home", engine.get(&page));
+    ///
+    /// tab.set("settings");
+    /// assert_eq!("contents of settings", engine.get(&page));
+    ///
+    /// // the branch for "home" is reused
+    /// tab.set("home");
+    /// assert_eq!("contents of home", engine.get(&page));
+    /// ```
+    #[track_caller]
+    pub fn then_memo<K, FK, FB, Out>(
+        &self,
+        key_fn: FK,
+        build_fn: FB,
+        capacity: usize,
+    ) -> Anchor<Out, E>
+    where
+        O1: 'static,
+        K: 'static + Eq + Hash,
+        FK: 'static + FnMut(&O1) -> K,
+        FB: 'static + FnMut(&K) -> Anchor<Out, E>,
+        Out: 'static,
+        E: Mount<ThenMemo<O1, K, FK, FB, Out, E>>,
+    {
+        E::mount(ThenMemo::new(
+            self.clone(),
+            key_fn,
+            build_fn,
+            capacity,
+            Location::caller(),
+        ))
+    }
+
     /// Creates an anchor that maps some input reference to some output reference.
     ///
     /// Performance is critical here: `f` will always be recalled any time any downstream node
@@ -170,6 +271,7 @@ where
         Out: 'static,
         F: 'static,
         RefMap<(Anchor<O1, E>,), F>: AnchorCore<E, Output = Out>,
+        E: Mount<RefMap<(Anchor<O1, E>,), F>>,
     {
         E::mount(RefMap::new((self.clone(),), f, Location::caller()))
     }
@@ -222,26 +324,98 @@ where
         Out: 'static,
         F: 'static,
         Cutoff<(Anchor<O1, E>,), F>: AnchorCore<E, Output = Out>,
+        E: Mount<Cutoff<(Anchor<O1, E>,), F>>,
     {
         E::mount(Cutoff::new((self.clone(),), f, Location::caller()))
     }
+
+    /// Creates an anchor that outputs the first value of this anchor, and never changes
+    /// afterwards.
+    ///
+    /// Once frozen, it drops this anchor, which stops being necessary on its behalf.
+    ///
+    /// ```
+    /// use anchors::single_threaded::*;
+    ///
+    /// let mut engine = Engine::new();
+    /// let num = Variable::new(1);
+    /// let frozen = num.watch().freeze();
+    /// assert_eq!(1, engine.get(&frozen));
+    ///
+    /// num.set(2);
+    /// assert_eq!(1, engine.get(&frozen));
+    /// ```
+    #[track_caller]
+    pub fn freeze(&self) -> Anchor<O1, E>
+    where
+        O1: Clone,
+        E: Mount<Freeze<O1, E>>,
+    {
+        E::mount(Freeze::new(self.clone(), Location::caller()))
+    }
+
+    /// Creates an anchor that outputs this anchor's value, except while `paused` is `true`,
+    /// when it keeps outputting the value it had when it was paused.
+    ///
+    /// While paused, this anchor stops being necessary on its behalf, so it isn't recalculated
+    /// unless something else needs it.
+    ///
+    /// ```
+    /// use anchors::single_threaded::*;
+    ///
+    /// let mut engine = Engine::new();
+    /// let num = Variable::new(1);
+    /// let hidden = Variable::new(false);
+    /// let shown = num.watch().pausable(&hidden.watch());
+    /// assert_eq!(1, engine.get(&shown));
+    ///
+    /// hidden.set(true);
+    /// num.set(2);
+    /// assert_eq!(1, engine.get(&shown));
+    ///
+    /// hidden.set(false);
+    /// assert_eq!(2, engine.get(&shown));
+    /// ```
+    #[track_caller]
+    pub fn pausable(&self, paused: &Anchor<bool, E>) -> Anchor<O1, E>
+    where
+        O1: Clone,
+        E: Mount<Pausable<O1, E>>,
+    {
+        E::mount(Pausable::new(
+            self.clone(),
+            paused.clone(),
+            Location::caller(),
+        ))
+    }
 }
 
-macro_rules! impl_tuple_ext {
-    ($([$output_type:ident, $num:tt])+) => {
-        impl <$($output_type,)+ E> Anchor<($($output_type,)+), E>
+macro_rules! impl_tuple_split {
+    ($tuple:ty; $([$output_type:ident, $num:tt])+) => {
+        impl <$($output_type,)+ E> Anchor<$tuple, E>
         where
             $(
                 $output_type: 'static + Clone + PartialEq,
+                E: Mount<RefMap<(Anchor<$tuple, E>,), fn(&$tuple) -> &$output_type>>,
             )+
             E: Engine,
         {
             pub fn split(&self) -> ($(Anchor<$output_type, E>,)+) {
-                ($(
-                    self.refmap(|v| &v.$num),
-                )+)
+                ($({
+                    let f: fn(&$tuple) -> &$output_type = |v| &v.$num;
+                    self.refmap(f)
+                },)+)
             }
         }
+    }
+}
+
+macro_rules! impl_tuple_ext {
+    ($([$output_type:ident, $num:tt])+) => {
+        impl_tuple_split! {
+            ($($output_type,)+);
+            $([$output_type, $num])+
+        }
 
         impl<$($output_type,)+ E> MultiAnchor<E> for ($(&Anchor<$output_type, E>,)+)
         where
@@ -258,6 +432,7 @@ macro_rules! impl_tuple_ext {
                 Out: 'static,
                 F: 'static,
                 Map<Self::Target, F, Out>: AnchorCore<E, Output=Out>,
+                E: Mount<Map<Self::Target, F, Out>>,
             {
                 E::mount(Map::new(
                     ($(self.$num.clone(),)+),
@@ -272,6 +447,7 @@ macro_rules! impl_tuple_ext {
                 Out: 'static,
                 F: 'static,
                 MapMut<Self::Target, F, Out>: AnchorCore<E, Output=Out>,
+                E: Mount<MapMut<Self::Target, F, Out>>,
             {
                 E::mount(MapMut::new(
                     ($(self.$num.clone(),)+),
@@ -287,6 +463,7 @@ macro_rules! impl_tuple_ext {
                 F: 'static,
                 Out: 'static,
                 Then<Self::Target, Out, F, E>: AnchorCore<E, Output=Out>,
+                E: Mount<Then<Self::Target, Out, F, E>>,
             {
                 E::mount(Then::new(
                     ($(self.$num.clone(),)+),
@@ -301,6 +478,7 @@ macro_rules! impl_tuple_ext {
                 Out: 'static,
                 F: 'static,
                 RefMap<Self::Target, F>: AnchorCore<E, Output = Out>,
+                E: Mount<RefMap<Self::Target, F>>,
             {
                 E::mount(RefMap::new(
                     ($(self.$num.clone(),)+),
@@ -315,6 +493,7 @@ macro_rules! impl_tuple_ext {
                 Out: 'static,
                 F: 'static,
                 Cutoff<Self::Target, F>: AnchorCore<E, Output = Out>,
+                E: Mount<Cutoff<Self::Target, F>>,
             {
                 E::mount(Cutoff::new(
                     ($(self.$num.clone(),)+),
@@ -1,4 +1,9 @@
 mod collect;
 
+pub use self::collect::*;
+
 #[cfg(feature = "im")]
 mod ord_map;
+
+#[cfg(feature = "im")]
+pub use self::ord_map::*;
@@ -1,7 +1,7 @@
 use std::{iter::FromIterator, panic::Location};
 
 use crate::{
-    core::{AnchorCore, AnchorHandle, Engine, OutputContext, Poll, UpdateContext},
+    core::{AnchorCore, AnchorHandle, Engine, Mount, OutputContext, Poll, UpdateContext},
     Anchor,
 };
 
@@ -9,7 +9,7 @@ impl<C, T, E> FromIterator<Anchor<T, E>> for Anchor<C, E>
 where
     C: 'static + FromIterator<T>,
     T: 'static + Clone,
-    E: Engine,
+    E: Engine + Mount<Collect<C, T, E>>,
 {
     fn from_iter<I>(iter: I) -> Self
     where
@@ -23,7 +23,7 @@ impl<'a, C, T, E> FromIterator<&'a Anchor<T, E>> for Anchor<C, E>
 where
     C: 'static + FromIterator<T>,
     T: 'static + Clone,
-    E: Engine,
+    E: Engine + Mount<Collect<C, T, E>>,
 {
     fn from_iter<I>(iter: I) -> Self
     where
@@ -33,7 +33,10 @@ where
     }
 }
 
-struct Collect<C, T, E: Engine> {
+/// A core anchor that collects the values of a number of incremental inputs.
+///
+/// Created by collecting an iterator of Anchors into an `Anchor<C, E>`.
+pub struct Collect<C, T, E: Engine> {
     anchors: Vec<Anchor<T, E>>,
     vals: Option<C>,
     location: &'static Location<'static>,
@@ -43,7 +46,7 @@ impl<C, T, E> Collect<C, T, E>
 where
     C: 'static + FromIterator<T>,
     T: 'static + Clone,
-    E: Engine,
+    E: Engine + Mount<Self>,
 {
     #[track_caller]
     pub fn new_anchor(anchors: Vec<Anchor<T, E>>) -> Anchor<C, E> {
@@ -69,10 +72,12 @@ where
 
     fn poll_updated(&mut self, ctx: &mut impl UpdateContext<Engine = E>) -> Poll {
         if self.vals.is_none() {
-            let pending_exists = self
-                .anchors
-                .iter()
-                .any(|anchor| ctx.request(anchor, true) == Poll::Pending);
+            // request every input, rather than stopping at the first pending one, so all of
+            // them get queued for recalculation at once
+            let mut pending_exists = false;
+            for anchor in &self.anchors {
+                pending_exists |= ctx.request(anchor, true) == Poll::Pending;
+            }
             if pending_exists {
                 return Poll::Pending;
             }
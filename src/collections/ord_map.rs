@@ -1,6 +1,11 @@
+use std::panic::Location;
+
 use im::{ordmap::DiffItem, OrdMap};
 
-use crate::{core::Engine, Anchor};
+use crate::{
+    core::{AnchorCore, AnchorHandle, Engine, Mount, OutputContext, Poll, UpdateContext},
+    Anchor,
+};
 
 impl<E, K, V> Anchor<OrdMap<K, V>, E>
 where
@@ -9,78 +14,239 @@ where
     V: Clone + PartialEq + 'static,
 {
     // TODO MERGE FN
-    pub fn inner_filter(
-        &self,
-        mut f: impl 'static + FnMut(&K, &V) -> bool,
-    ) -> Anchor<OrdMap<K, V>, E> {
-        self.inner_filter_map(move |k, v| if f(k, v) { Some(v.clone()) } else { None })
+    #[track_caller]
+    pub fn inner_filter<F>(&self, f: F) -> Anchor<OrdMap<K, V>, E>
+    where
+        F: 'static + FnMut(&K, &V) -> bool,
+        E: Mount<UnorderedFold<K, V, OrdMap<K, V>, InnerFilter<F>, E>>,
+    {
+        UnorderedFold::new_anchor(self.clone(), OrdMap::new(), InnerFilter(f))
     }
 
-    pub fn inner_map<T>(&self, mut f: impl 'static + FnMut(&K, &V) -> T) -> Anchor<OrdMap<K, T>, E>
+    #[track_caller]
+    pub fn inner_map<T, F>(&self, f: F) -> Anchor<OrdMap<K, T>, E>
     where
         T: 'static + Clone + PartialEq,
+        F: 'static + FnMut(&K, &V) -> T,
+        E: Mount<UnorderedFold<K, V, OrdMap<K, T>, InnerMap<F>, E>>,
     {
-        self.inner_filter_map(move |k, v| Some(f(k, v)))
+        UnorderedFold::new_anchor(self.clone(), OrdMap::new(), InnerMap(f))
     }
 
-    pub fn inner_filter_map<T>(
-        &self,
-        mut f: impl 'static + FnMut(&K, &V) -> Option<T>,
-    ) -> Anchor<OrdMap<K, T>, E>
+    #[track_caller]
+    pub fn inner_filter_map<T, F>(&self, f: F) -> Anchor<OrdMap<K, T>, E>
     where
         T: 'static + Clone + PartialEq,
+        F: 'static + FnMut(&K, &V) -> Option<T>,
+        E: Mount<UnorderedFold<K, V, OrdMap<K, T>, InnerFilterMap<F>, E>>,
     {
-        self.inner_unordered_fold(OrdMap::new(), move |out, diff_item| {
-            match diff_item {
-                DiffItem::Add(k, v) => {
-                    if let Some(new) = f(k, v) {
-                        out.insert(k.clone(), new);
-                        return true;
-                    }
-                }
-                DiffItem::Update {
-                    new: (k, v),
-                    old: _,
-                } => {
-                    if let Some(new) = f(k, v) {
-                        out.insert(k.clone(), new);
-                        return true;
-                    } else if out.contains_key(k) {
-                        out.remove(k);
-                        return true;
-                    }
-                }
-                DiffItem::Remove(k, _v) => {
-                    out.remove(k);
-                    return true;
-                }
-            }
-            false
-        })
+        UnorderedFold::new_anchor(self.clone(), OrdMap::new(), InnerFilterMap(f))
     }
 
-    pub fn inner_unordered_fold<T>(
-        &self,
-        initial_state: T,
-        mut f: impl 'static + for<'a> FnMut(&mut T, DiffItem<'a, K, V>) -> bool,
-    ) -> Anchor<T, E>
+    #[track_caller]
+    pub fn inner_unordered_fold<T, F>(&self, initial_state: T, f: F) -> Anchor<T, E>
     where
         T: 'static + PartialEq + Clone,
+        F: 'static + for<'a> FnMut(&mut T, DiffItem<'a, K, V>) -> bool,
+        E: Mount<UnorderedFold<K, V, T, F, E>>,
     {
-        let mut last_observation = OrdMap::new();
-        self.map_mut(initial_state, move |out, this| {
-            let mut did_update = false;
-            for item in last_observation.diff(this) {
-                if f(out, item) {
-                    did_update = true;
-                }
+        UnorderedFold::new_anchor(self.clone(), initial_state, f)
+    }
+}
+
+/// Folds a change of an `OrdMap` into some state, returning whether the state changed.
+///
+/// Implemented by closures taking the state and a `DiffItem`, and by the folds of
+/// `inner_filter`, `inner_map` and `inner_filter_map`.
+pub trait DiffFold<K, V, T> {
+    fn fold(&mut self, state: &mut T, item: DiffItem<'_, K, V>) -> bool;
+}
+
+impl<K, V, T, F> DiffFold<K, V, T> for F
+where
+    F: for<'a> FnMut(&mut T, DiffItem<'a, K, V>) -> bool,
+{
+    fn fold(&mut self, state: &mut T, item: DiffItem<'_, K, V>) -> bool {
+        self(state, item)
+    }
+}
+
+/// The `DiffFold` of `inner_filter`.
+pub struct InnerFilter<F>(F);
+
+impl<K, V, F> DiffFold<K, V, OrdMap<K, V>> for InnerFilter<F>
+where
+    K: Ord + Clone,
+    V: Clone,
+    F: FnMut(&K, &V) -> bool,
+{
+    fn fold(&mut self, state: &mut OrdMap<K, V>, item: DiffItem<'_, K, V>) -> bool {
+        let f = &mut self.0;
+        fold_filter_map(
+            state,
+            item,
+            |k, v| if f(k, v) { Some(v.clone()) } else { None },
+        )
+    }
+}
+
+/// The `DiffFold` of `inner_map`.
+pub struct InnerMap<F>(F);
+
+impl<K, V, T, F> DiffFold<K, V, OrdMap<K, T>> for InnerMap<F>
+where
+    K: Ord + Clone,
+    T: Clone,
+    F: FnMut(&K, &V) -> T,
+{
+    fn fold(&mut self, state: &mut OrdMap<K, T>, item: DiffItem<'_, K, V>) -> bool {
+        let f = &mut self.0;
+        fold_filter_map(state, item, |k, v| Some(f(k, v)))
+    }
+}
+
+/// The `DiffFold` of `inner_filter_map`.
+pub struct InnerFilterMap<F>(F);
+
+impl<K, V, T, F> DiffFold<K, V, OrdMap<K, T>> for InnerFilterMap<F>
+where
+    K: Ord + Clone,
+    T: Clone,
+    F: FnMut(&K, &V) -> Option<T>,
+{
+    fn fold(&mut self, state: &mut OrdMap<K, T>, item: DiffItem<'_, K, V>) -> bool {
+        fold_filter_map(state, item, &mut self.0)
+    }
+}
+
+fn fold_filter_map<K, V, T>(
+    out: &mut OrdMap<K, T>,
+    diff_item: DiffItem<'_, K, V>,
+    mut f: impl FnMut(&K, &V) -> Option<T>,
+) -> bool
+where
+    K: Ord + Clone,
+    T: Clone,
+{
+    match diff_item {
+        DiffItem::Add(k, v) => {
+            if let Some(new) = f(k, v) {
+                out.insert(k.clone(), new);
+                return true;
             }
-            last_observation = this.clone();
-            did_update
+        }
+        DiffItem::Update {
+            new: (k, v),
+            old: _,
+        } => {
+            if let Some(new) = f(k, v) {
+                out.insert(k.clone(), new);
+                return true;
+            } else if out.contains_key(k) {
+                out.remove(k);
+                return true;
+            }
+        }
+        DiffItem::Remove(k, _v) => {
+            out.remove(k);
+            return true;
+        }
+    }
+    false
+}
+
+/// A core anchor that folds the changes of an `OrdMap` since it was last recalculated into
+/// some state.
+///
+/// Created by `inner_unordered_fold`, `inner_filter`, `inner_map` and `inner_filter_map`.
+pub struct UnorderedFold<K, V, T, D, E: Engine> {
+    anchor: Anchor<OrdMap<K, V>, E>,
+    fold: D,
+    last_observation: OrdMap<K, V>,
+    output: T,
+    output_stale: bool,
+    location: &'static Location<'static>,
+}
+
+impl<K, V, T, D, E> UnorderedFold<K, V, T, D, E>
+where
+    K: 'static + Ord + Clone,
+    V: 'static + Clone + PartialEq,
+    T: 'static + PartialEq,
+    D: 'static + DiffFold<K, V, T>,
+    E: Engine + Mount<Self>,
+{
+    #[track_caller]
+    fn new_anchor(anchor: Anchor<OrdMap<K, V>, E>, initial: T, fold: D) -> Anchor<T, E> {
+        E::mount(Self {
+            anchor,
+            fold,
+            last_observation: OrdMap::new(),
+            output: initial,
+            output_stale: true,
+            location: Location::caller(),
         })
     }
 }
 
+impl<K, V, T, D, E> AnchorCore<E> for UnorderedFold<K, V, T, D, E>
+where
+    K: 'static + Ord + Clone,
+    V: 'static + Clone + PartialEq,
+    T: 'static + PartialEq,
+    D: 'static + DiffFold<K, V, T>,
+    E: Engine,
+{
+    type Output = T;
+
+    fn mark_dirty(&mut self, _edge: <E::AnchorHandle as AnchorHandle>::AnchorKey) {
+        self.output_stale = true;
+    }
+
+    fn poll_updated(&mut self, ctx: &mut impl UpdateContext<Engine = E>) -> Poll {
+        if !self.output_stale {
+            return Poll::Unchanged;
+        }
+        match ctx.request(&self.anchor, true) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Unchanged => {
+                self.output_stale = false;
+                return Poll::Unchanged;
+            }
+            Poll::Updated => {}
+        }
+        self.output_stale = false;
+
+        let this = ctx.get(&self.anchor);
+        let mut did_update = false;
+        for item in self.last_observation.diff(this) {
+            if self.fold.fold(&mut self.output, item) {
+                did_update = true;
+            }
+        }
+        self.last_observation = this.clone();
+        if did_update {
+            Poll::Updated
+        } else {
+            Poll::Unchanged
+        }
+    }
+
+    fn output<'slf, 'out>(
+        &'slf self,
+        _ctx: &mut impl OutputContext<'out, Engine = E>,
+    ) -> &'out Self::Output
+    where
+        'slf: 'out,
+    {
+        &self.output
+    }
+
+    fn debug_location(&self) -> Option<(&'static str, &'static Location<'static>)> {
+        Some(("inner_unordered_fold", self.location))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -170,4 +336,31 @@ mod test {
         assert_eq!(Some(&4), b_map.get("c"));
         assert_eq!(Some(&13), b_map.get("e"));
     }
+
+    #[test]
+    fn test_filter_map_sync() {
+        use crate::sync::{Engine, Variable};
+
+        let mut engine = Engine::new();
+
+        let mut a_map = OrdMap::new();
+        a_map.insert("a".to_string(), 1);
+        a_map.insert("b".to_string(), 23);
+        let a = Variable::new(a_map.clone());
+
+        let b = a
+            .watch()
+            .inner_filter_map(|_, n| if *n > 10 { Some(n * 2) } else { None });
+        let b_map = engine.get(&b);
+        assert_eq!(1, b_map.len());
+        assert_eq!(Some(&46), b_map.get("b"));
+
+        a_map.insert("a".to_string(), 25);
+        a_map.insert("b".to_string(), 5);
+        a.set(a_map.clone());
+
+        let b_map = engine.get(&b);
+        assert_eq!(1, b_map.len());
+        assert_eq!(Some(&50), b_map.get("a"));
+    }
 }
@@ -4,17 +4,41 @@
 //! you should never need to import things from here. The crate root should re-export anything
 //! you need to use `anchors`!
 
-use std::{fmt::Debug, hash::Hash, panic::Location};
+use std::{fmt::Debug, hash::Hash, panic::Location, task::Waker};
 
-use crate::Anchor;
+use crate::{Anchor, Error};
 
+mod clock;
+mod constant;
 mod cutoff;
+mod debug_info;
+mod freeze;
+mod from_future;
+mod generation;
 mod map;
 mod map_mut;
+mod pausable;
 mod refmap;
+#[cfg(feature = "serde")]
+mod snapshot;
 mod then;
+mod then_memo;
+mod transaction;
+mod variable;
 
-pub use self::{cutoff::*, map::*, map_mut::*, refmap::*, then::*};
+pub use self::{
+    clock::*, constant::*, cutoff::*, debug_info::*, freeze::*, from_future::*, generation::*,
+    map::*, map_mut::*, pausable::*, refmap::*, then::*, then_memo::*, transaction::*, variable::*,
+};
+
+#[cfg(feature = "serde")]
+pub use self::snapshot::{Snapshot, SnapshotError};
+#[cfg(feature = "serde")]
+pub(crate) use self::snapshot::{SnapshotEntry, SnapshotRegistry};
+
+pub use crate::collections::Collect;
+#[cfg(feature = "im")]
+pub use crate::collections::{DiffFold, InnerFilter, InnerFilterMap, InnerMap, UnorderedFold};
 
 /// Indicates whether a value is ready for reading, and if it is, whether it's changed
 /// since the last read.
@@ -28,11 +52,30 @@ pub enum Poll {
     /// the value is unchanged since the previous read.
     Unchanged,
 
-    /// Indicates the polled value is not ready for reading, but has been queued for recalculation.
+    /// Indicates the polled value is not ready for reading, but has been queued for recalculation,
+    /// or is waiting for some future to wake it up.
     /// The output value will eventually switch to Updated or Unchanged.
     Pending,
 }
 
+/// Indicates whether the node is a part of some observed calculation.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ObservedState {
+    /// The node has been marked as observed directly via `mark_observed`.
+    Observed,
+
+    /// The node is not marked as observed directly.
+    /// However, the node has some descendent that is Observed, and this node has
+    /// been recalculated since that descendent become Observed.
+    Necessary,
+
+    /// The node is not marked as observed directly.
+    /// Additionally, this node either has no Observed descendent, or the chain linking
+    /// this node to that Observed descendent has not been recalculated since that
+    /// descendent become observed.
+    Unnecessary,
+}
+
 /// A handle to a particular `AnchorCore`.
 ///
 /// Each engine implements its own.
@@ -49,16 +92,126 @@ pub trait AnchorHandle: Sized + Clone {
 
 /// The core engine trait implemented by each recalculation engine.
 ///
-/// Allows mounting an `AnchorCore`
-/// into an actual `Anchor`, although this mounting should usually be done by each `AnchorCore`
-/// implementation directly.
+/// Anchors are mounted onto an engine through its `Mount` implementations.
 pub trait Engine: 'static {
     type AnchorHandle: AnchorHandle;
     type DirtyHandle: DirtyHandle;
+}
+
+/// An engine whose update contexts implement `UpdateContext::begin_scope` and `end_scope`,
+/// which `Anchor::then_scoped` relies on.
+///
+/// The `sync` engine doesn't support scopes, so scoped binds don't compile on it:
+///
+/// ```compile_fail
+/// use anchors::sync::*;
+///
+/// let num = Variable::new(1);
+/// let res = num.watch().then_scoped(|num| Anchor::constant(*num));
+/// ```
+pub trait ScopedEngine: Engine {}
+
+/// The user-facing API shared by all engines, which allows code to be written generically over
+/// the engine it runs on.
+///
+/// ```
+/// use anchors::{core::Runtime, single_threaded, sync, Anchor};
+///
+/// fn doubled<E: Runtime>(engine: &mut E, anchor: &Anchor<usize, E>) -> usize {
+///     engine.get(anchor) * 2
+/// }
+///
+/// let mut engine = single_threaded::Engine::new();
+/// let variable = single_threaded::Variable::new(1);
+/// assert_eq!(doubled(&mut engine, &variable.watch()), 2);
+///
+/// let mut engine = sync::Engine::new();
+/// let variable = sync::Variable::new(2);
+/// assert_eq!(doubled(&mut engine, &variable.watch()), 4);
+/// ```
+pub trait Runtime: Engine {
+    /// Marks an Anchor as observed. All observed nodes will always be brought up-to-date
+    /// when *any* Anchor in the graph is retrieved.
+    fn mark_observed<O>(&mut self, anchor: &Anchor<O, Self>)
+    where
+        O: 'static,
+    {
+        self.try_mark_observed(anchor)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Marks an Anchor as observed like `Runtime::mark_observed`, but reports misuse as an
+    /// error instead of panicking.
+    fn try_mark_observed<O>(&mut self, anchor: &Anchor<O, Self>) -> Result<(), Error>
+    where
+        O: 'static;
+
+    /// Marks an Anchor as unobserved. If the `anchor` has parents that are necessary
+    /// because `anchor` was previously observed, those parents will be unmarked as
+    /// necessary.
+    fn mark_unobserved<O>(&mut self, anchor: &Anchor<O, Self>)
+    where
+        O: 'static,
+    {
+        self.try_mark_unobserved(anchor)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
 
-    fn mount<I>(inner: I) -> Anchor<I::Output, Self>
+    /// Marks an Anchor as unobserved like `Runtime::mark_unobserved`, but reports misuse as an
+    /// error instead of panicking.
+    fn try_mark_unobserved<O>(&mut self, anchor: &Anchor<O, Self>) -> Result<(), Error>
     where
-        I: 'static + AnchorCore<Self>;
+        O: 'static;
+
+    /// Returns whether an Anchor is Observed, Necessary, or Unnecessary.
+    fn check_observed<O>(&self, anchor: &Anchor<O, Self>) -> ObservedState;
+
+    /// Retrieves the value of an Anchor, recalculating dependencies as necessary to get the
+    /// latest value.
+    fn get<O>(&mut self, anchor: &Anchor<O, Self>) -> O
+    where
+        O: 'static + Clone,
+    {
+        self.try_get(anchor).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Retrieves the value of an Anchor like `Runtime::get`, but reports misuse of the engine,
+    /// such as cyclic anchors, as an error instead of panicking.
+    fn try_get<O>(&mut self, anchor: &Anchor<O, Self>) -> Result<O, Error>
+    where
+        O: 'static + Clone;
+
+    /// Ensure any Observed nodes are up-to-date, recalculating dependencies as necessary.
+    fn stabilize(&mut self) {
+        self.try_stabilize().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Ensure any Observed nodes are up-to-date like `Runtime::stabilize`, but reports misuse
+    /// of the engine, such as cyclic anchors, as an error instead of panicking.
+    fn try_stabilize(&mut self) -> Result<(), Error>;
+
+    /// Runs `f` with a `Transaction`, whose staged variable writes are applied all at once
+    /// after `f` returned, unless they were rolled back.
+    ///
+    /// If `f` panics, none of them are applied.
+    fn transaction<R>(&mut self, f: impl FnOnce(&mut Transaction<Self>) -> R) -> R {
+        let mut transaction = Transaction::new();
+        let res = f(&mut transaction);
+        transaction.commit();
+        res
+    }
+}
+
+/// Allows mounting an `AnchorCore` into an actual `Anchor`, although this mounting should
+/// usually be done by each `AnchorCore` implementation directly.
+///
+/// Engines that keep their graph on a single thread can mount any core, while thread-safe
+/// engines only accept cores that can be shared between threads.
+pub trait Mount<I>: Engine
+where
+    I: AnchorCore<Self>,
+{
+    fn mount(inner: I) -> Anchor<I::Output, Self>;
 }
 
 /// Allows a node with non-Anchors inputs to manually mark itself as dirty.
@@ -123,6 +276,25 @@ pub trait UpdateContext {
     /// For instance, perhaps this `AnchorCore`s value represents the current time, or
     /// it's a `Variable` that has a setter function.
     fn dirty_handle(&mut self) -> <Self::Engine as Engine>::DirtyHandle;
+
+    /// Returns a waker that marks `self`'s output as dirty when woken, from any thread.
+    ///
+    /// Use it to poll non-anchor futures from `poll_updated`, returning `Poll::Pending` until
+    /// they're ready. The engine polls `self` again once the waker was woken.
+    fn waker(&mut self) -> Waker;
+
+    /// Starts a scope, which anchors mounted until `end_scope` belong to.
+    ///
+    /// Anchors belonging to the scope `self` started before are invalidated: they're dropped
+    /// from the graph's calculations, and using them afterwards is reported as
+    /// `Error::Invalidated`. `then_scoped` wraps each call of its closure in a scope, so
+    /// branches it built before don't linger once it rebuilt them.
+    ///
+    /// Engines that don't support scopes ignore this; those that do implement `ScopedEngine`.
+    fn begin_scope(&mut self) {}
+
+    /// Ends the scope started by `begin_scope`.
+    fn end_scope(&mut self) {}
 }
 
 /// The engine-agnostic implementation of each type of Anchor.
@@ -144,9 +316,10 @@ where
     /// Called by the engine when it wants to know if this value has changed or
     /// not.
     ///
-    /// If some requested value from `ctx` is `Pending`, this method should
-    /// return `Poll::Pending`; otherwise it must finish recalculation and report
-    /// either `Poll::Updated` or `Poll::Unchanged`.
+    /// If some requested value from `ctx` is `Pending`, or some future polled with
+    /// `ctx.waker()` is not ready yet, this method should return `Poll::Pending`;
+    /// otherwise it must finish recalculation and report either `Poll::Updated` or
+    /// `Poll::Unchanged`.
     fn poll_updated(&mut self, ctx: &mut impl UpdateContext<Engine = E>) -> Poll;
 
     /// Called by the engine to get the current output value of this `AnchorCore`.
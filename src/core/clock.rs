@@ -0,0 +1,294 @@
+use std::{
+    panic::Location,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    core::{
+        AnchorCore, AnchorHandle, Engine, Mount, OutputContext, Poll, UpdateContext, VarAnchor,
+        Variable,
+    },
+    Anchor,
+};
+
+/// A virtual clock, which only moves when it's told to.
+///
+/// Anchors derived from it only depend on the clock's current time, so they behave the same
+/// way whenever they're recalculated, which makes them deterministic in tests.
+pub struct Clock<E: Engine> {
+    now: Variable<Instant, E>,
+}
+
+impl<E: Engine> Clone for Clock<E> {
+    fn clone(&self) -> Self {
+        Self {
+            now: self.now.clone(),
+        }
+    }
+}
+
+impl<E> Clock<E>
+where
+    E: Mount<VarAnchor<Instant, E>>,
+{
+    /// Creates a new clock, whose current time is `start`.
+    #[track_caller]
+    pub fn new(start: Instant) -> Self {
+        Self {
+            now: Variable::new(start),
+        }
+    }
+}
+
+impl<E: Engine> Clock<E> {
+    /// Moves the clock forward to `instant`.
+    ///
+    /// Panics if `instant` is earlier than the clock's current time.
+    pub fn advance_to(&self, instant: Instant) {
+        assert!(
+            instant >= self.current(),
+            "attempted to move the clock backwards"
+        );
+        self.now.set_if_changed(instant);
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance_by(&self, duration: Duration) {
+        self.advance_to(self.current() + duration);
+    }
+
+    /// Returns the clock's current time, without going through the engine.
+    pub fn current(&self) -> Instant {
+        *self.now.get()
+    }
+
+    /// An anchor for the clock's current time.
+    pub fn now(&self) -> Anchor<Instant, E> {
+        self.now.watch()
+    }
+
+    /// An anchor telling whether the clock is before or after `instant`.
+    ///
+    /// It only updates once, when the clock reaches `instant`.
+    #[track_caller]
+    pub fn at(&self, instant: Instant) -> Anchor<BeforeOrAfter, E>
+    where
+        E: Mount<At<E>>,
+    {
+        E::mount(At {
+            now: self.now(),
+            instant,
+            output: None,
+            location: Location::caller(),
+        })
+    }
+
+    /// An anchor counting how many times `period` has passed since it was created.
+    ///
+    /// It updates once the clock reached the end of each period, no matter how many periods
+    /// the clock skipped at once.
+    #[track_caller]
+    pub fn every(&self, period: Duration) -> Anchor<u64, E>
+    where
+        E: Mount<Every<E>>,
+    {
+        assert!(
+            period > Duration::ZERO,
+            "attempted to tick every zero seconds"
+        );
+        E::mount(Every {
+            now: self.now(),
+            start: self.current(),
+            period,
+            output: None,
+            location: Location::caller(),
+        })
+    }
+}
+
+impl<O, E> Anchor<O, E>
+where
+    O: 'static,
+    E: Engine,
+{
+    /// Creates an anchor that outputs this anchor's value once it stopped changing for
+    /// `duration`, as measured by `clock`.
+    ///
+    /// Its first value is output right away.
+    #[track_caller]
+    pub fn debounce(&self, clock: &Clock<E>, duration: Duration) -> Anchor<O, E>
+    where
+        O: Clone,
+        E: Mount<Debounce<O, E>>,
+    {
+        E::mount(Debounce {
+            anchor: self.clone(),
+            now: clock.now(),
+            duration,
+            output: None,
+            pending: None,
+            location: Location::caller(),
+        })
+    }
+}
+
+/// Whether a clock is before or after some point in time.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum BeforeOrAfter {
+    Before,
+    After,
+}
+
+/// A core anchor telling whether a clock is before or after some point in time.
+pub struct At<E: Engine> {
+    now: Anchor<Instant, E>,
+    instant: Instant,
+    output: Option<BeforeOrAfter>,
+    location: &'static Location<'static>,
+}
+
+impl<E: Engine> AnchorCore<E> for At<E> {
+    type Output = BeforeOrAfter;
+
+    fn mark_dirty(&mut self, _edge: <E::AnchorHandle as AnchorHandle>::AnchorKey) {}
+
+    fn poll_updated(&mut self, ctx: &mut impl UpdateContext<Engine = E>) -> Poll {
+        if ctx.request(&self.now, true) == Poll::Pending {
+            return Poll::Pending;
+        }
+        let output = if *ctx.get(&self.now) < self.instant {
+            BeforeOrAfter::Before
+        } else {
+            BeforeOrAfter::After
+        };
+        if self.output.replace(output) == Some(output) {
+            Poll::Unchanged
+        } else {
+            Poll::Updated
+        }
+    }
+
+    fn output<'slf, 'out>(
+        &'slf self,
+        _ctx: &mut impl OutputContext<'out, Engine = E>,
+    ) -> &'out Self::Output
+    where
+        'slf: 'out,
+    {
+        self.output
+            .as_ref()
+            .expect("output called on an `at` that wasn't polled yet")
+    }
+
+    fn debug_location(&self) -> Option<(&'static str, &'static Location<'static>)> {
+        Some(("at", self.location))
+    }
+}
+
+/// A core anchor counting how many periods passed on a clock.
+pub struct Every<E: Engine> {
+    now: Anchor<Instant, E>,
+    start: Instant,
+    period: Duration,
+    output: Option<u64>,
+    location: &'static Location<'static>,
+}
+
+impl<E: Engine> AnchorCore<E> for Every<E> {
+    type Output = u64;
+
+    fn mark_dirty(&mut self, _edge: <E::AnchorHandle as AnchorHandle>::AnchorKey) {}
+
+    fn poll_updated(&mut self, ctx: &mut impl UpdateContext<Engine = E>) -> Poll {
+        if ctx.request(&self.now, true) == Poll::Pending {
+            return Poll::Pending;
+        }
+        let elapsed = ctx.get(&self.now).saturating_duration_since(self.start);
+        let output = (elapsed.as_nanos() / self.period.as_nanos()) as u64;
+        if self.output.replace(output) == Some(output) {
+            Poll::Unchanged
+        } else {
+            Poll::Updated
+        }
+    }
+
+    fn output<'slf, 'out>(
+        &'slf self,
+        _ctx: &mut impl OutputContext<'out, Engine = E>,
+    ) -> &'out Self::Output
+    where
+        'slf: 'out,
+    {
+        self.output
+            .as_ref()
+            .expect("output called on an `every` that wasn't polled yet")
+    }
+
+    fn debug_location(&self) -> Option<(&'static str, &'static Location<'static>)> {
+        Some(("every", self.location))
+    }
+}
+
+/// A core anchor that outputs its input once it stopped changing for a while.
+pub struct Debounce<O, E: Engine> {
+    anchor: Anchor<O, E>,
+    now: Anchor<Instant, E>,
+    duration: Duration,
+    output: Option<O>,
+    // the latest input, and when it arrived
+    pending: Option<(O, Instant)>,
+    location: &'static Location<'static>,
+}
+
+impl<O, E> AnchorCore<E> for Debounce<O, E>
+where
+    O: 'static + Clone,
+    E: Engine,
+{
+    type Output = O;
+
+    fn mark_dirty(&mut self, _edge: <E::AnchorHandle as AnchorHandle>::AnchorKey) {}
+
+    fn poll_updated(&mut self, ctx: &mut impl UpdateContext<Engine = E>) -> Poll {
+        let input = ctx.request(&self.anchor, true);
+        let now = ctx.request(&self.now, true);
+        if input == Poll::Pending || now == Poll::Pending {
+            return Poll::Pending;
+        }
+        let now = *ctx.get(&self.now);
+
+        if self.output.is_none() {
+            self.output = Some(ctx.get(&self.anchor).clone());
+            return Poll::Updated;
+        }
+        if input == Poll::Updated {
+            self.pending = Some((ctx.get(&self.anchor).clone(), now));
+        }
+        match self.pending.take() {
+            Some((value, since)) if now >= since + self.duration => {
+                self.output = Some(value);
+                Poll::Updated
+            }
+            pending => {
+                self.pending = pending;
+                Poll::Unchanged
+            }
+        }
+    }
+
+    fn output<'slf, 'out>(
+        &'slf self,
+        _ctx: &mut impl OutputContext<'out, Engine = E>,
+    ) -> &'out Self::Output
+    where
+        'slf: 'out,
+    {
+        self.output
+            .as_ref()
+            .expect("output called on a `debounce` that wasn't polled yet")
+    }
+
+    fn debug_location(&self) -> Option<(&'static str, &'static Location<'static>)> {
+        Some(("debounce", self.location))
+    }
+}
@@ -0,0 +1,108 @@
+use std::{panic::Location, sync::Arc};
+
+use crate::{
+    core::{AnchorCore, AnchorHandle, Engine, Mount, OutputContext, Poll, UpdateContext},
+    Anchor,
+};
+
+/// A constant that exposes an anchor for its value, on any engine.
+///
+/// Engines usually re-export it with their engine filled in, e.g. as
+/// `single_threaded::Constant<T>`.
+pub struct Constant<T, E: Engine> {
+    value: Arc<T>,
+    anchor: Anchor<T, E>,
+}
+
+impl<T, E: Engine> Clone for Constant<T, E> {
+    fn clone(&self) -> Self {
+        Self {
+            value: Arc::clone(&self.value),
+            anchor: self.anchor.clone(),
+        }
+    }
+}
+
+impl<T, E> Constant<T, E>
+where
+    T: 'static,
+    E: Mount<ConstAnchor<T>>,
+{
+    /// Creates a new Const
+    #[track_caller]
+    pub fn new(value: T) -> Self {
+        let value = Arc::new(value);
+        Constant {
+            value: Arc::clone(&value),
+            anchor: E::mount(ConstAnchor::new(value, Location::caller())),
+        }
+    }
+}
+
+impl<T, E: Engine> Constant<T, E> {
+    /// Retrieves the value
+    pub fn get(&self) -> Arc<T> {
+        Arc::clone(&self.value)
+    }
+
+    pub fn watch(&self) -> Anchor<T, E> {
+        self.anchor.clone()
+    }
+
+    pub fn into_anchor(self) -> Anchor<T, E> {
+        self.anchor
+    }
+}
+
+/// A core anchor for values that never change.
+pub struct ConstAnchor<T> {
+    value: Arc<T>,
+    location: &'static Location<'static>,
+    first_poll: bool,
+}
+
+impl<T> ConstAnchor<T> {
+    pub fn new(value: Arc<T>, location: &'static Location<'static>) -> Self {
+        Self {
+            value,
+            location,
+            first_poll: true,
+        }
+    }
+}
+
+impl<T, E> AnchorCore<E> for ConstAnchor<T>
+where
+    T: 'static,
+    E: Engine,
+{
+    type Output = T;
+
+    fn mark_dirty(&mut self, _edge: <E::AnchorHandle as AnchorHandle>::AnchorKey) {
+        panic!("attempt to mark a constant's non-existent inputs as as dirty")
+    }
+
+    fn poll_updated(&mut self, _ctx: &mut impl UpdateContext<Engine = E>) -> Poll {
+        let poll = if self.first_poll {
+            Poll::Updated
+        } else {
+            Poll::Unchanged
+        };
+        self.first_poll = false;
+        poll
+    }
+
+    fn output<'slf, 'out>(
+        &'slf self,
+        _ctx: &mut impl OutputContext<'out, Engine = E>,
+    ) -> &'out Self::Output
+    where
+        'slf: 'out,
+    {
+        &self.value
+    }
+
+    fn debug_location(&self) -> Option<(&'static str, &'static Location<'static>)> {
+        Some(("Constant", self.location))
+    }
+}
@@ -0,0 +1,29 @@
+use std::panic::Location;
+
+/// Debug information about a mounted `AnchorCore`, as reported by engines.
+#[derive(Copy, Clone, Debug)]
+pub struct AnchorDebugInfo {
+    pub(crate) location: Option<(&'static str, &'static Location<'static>)>,
+    pub(crate) type_info: &'static str,
+}
+
+impl AnchorDebugInfo {
+    /// The name of the anchor's combinator and where it was created, if known.
+    pub fn location(&self) -> Option<(&'static str, &'static Location<'static>)> {
+        self.location
+    }
+
+    /// The type name of the anchor's core.
+    pub fn type_info(&self) -> &'static str {
+        self.type_info
+    }
+}
+
+impl std::fmt::Display for AnchorDebugInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.location {
+            Some((name, location)) => write!(f, "{location} ({name})"),
+            None => write!(f, "{}", self.type_info),
+        }
+    }
+}
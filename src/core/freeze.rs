@@ -0,0 +1,65 @@
+use std::panic::Location;
+
+use crate::core::{Anchor, AnchorCore, AnchorHandle, Engine, OutputContext, Poll, UpdateContext};
+
+/// A core anchor that outputs the first value of its input, and then never changes again.
+///
+/// Once it has its value, it unrequests and drops its input, so it no longer keeps it
+/// necessary or alive.
+pub struct Freeze<O, E: Engine> {
+    pub(super) anchor: Option<Anchor<O, E>>,
+    pub(super) output: Option<O>,
+    pub(super) location: &'static Location<'static>,
+}
+
+impl<O, E: Engine> Freeze<O, E> {
+    pub fn new(anchor: Anchor<O, E>, location: &'static Location<'static>) -> Self {
+        Self {
+            anchor: Some(anchor),
+            output: None,
+            location,
+        }
+    }
+}
+
+impl<O, E> AnchorCore<E> for Freeze<O, E>
+where
+    O: 'static + Clone,
+    E: Engine,
+{
+    type Output = O;
+
+    fn mark_dirty(&mut self, _edge: <E::AnchorHandle as AnchorHandle>::AnchorKey) {
+        // noop
+    }
+
+    fn poll_updated(&mut self, ctx: &mut impl UpdateContext<Engine = E>) -> Poll {
+        let anchor = match &self.anchor {
+            Some(anchor) => anchor,
+            None => return Poll::Unchanged,
+        };
+        if ctx.request(anchor, true) == Poll::Pending {
+            return Poll::Pending;
+        }
+        self.output = Some(ctx.get(anchor).clone());
+        ctx.unrequest(anchor);
+        self.anchor = None;
+        Poll::Updated
+    }
+
+    fn output<'slf, 'out>(
+        &'slf self,
+        _ctx: &mut impl OutputContext<'out, Engine = E>,
+    ) -> &'out Self::Output
+    where
+        'slf: 'out,
+    {
+        self.output
+            .as_ref()
+            .expect("output called on a `freeze` that wasn't polled yet")
+    }
+
+    fn debug_location(&self) -> Option<(&'static str, &'static Location<'static>)> {
+        Some(("freeze", self.location))
+    }
+}
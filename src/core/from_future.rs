@@ -0,0 +1,74 @@
+use std::{
+    future::Future,
+    panic::Location,
+    pin::Pin,
+    task::{self, Context},
+};
+
+use crate::core::{AnchorCore, AnchorHandle, Engine, OutputContext, Poll, UpdateContext};
+
+/// A core anchor that outputs the value of some non-anchor `Future`, once it resolved.
+///
+/// Until then it reports `Poll::Pending`, and the engine polls it again whenever the future
+/// wakes it up.
+pub struct FromFuture<F: Future> {
+    future: Option<Pin<Box<F>>>,
+    output: Option<F::Output>,
+    location: &'static Location<'static>,
+}
+
+impl<F: Future> FromFuture<F> {
+    pub fn new(future: F, location: &'static Location<'static>) -> Self {
+        Self {
+            future: Some(Box::pin(future)),
+            output: None,
+            location,
+        }
+    }
+}
+
+impl<F, E> AnchorCore<E> for FromFuture<F>
+where
+    F: Future,
+    F::Output: 'static,
+    E: Engine,
+{
+    type Output = F::Output;
+
+    fn mark_dirty(&mut self, _edge: <E::AnchorHandle as AnchorHandle>::AnchorKey) {
+        panic!("attempt to mark a future's non-existent inputs as dirty")
+    }
+
+    fn poll_updated(&mut self, ctx: &mut impl UpdateContext<Engine = E>) -> Poll {
+        let future = match &mut self.future {
+            Some(future) => future,
+            // resolved already, so we were woken up spuriously
+            None => return Poll::Unchanged,
+        };
+        let waker = ctx.waker();
+        match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            task::Poll::Ready(output) => {
+                self.output = Some(output);
+                self.future = None;
+                Poll::Updated
+            }
+            task::Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn output<'slf, 'out>(
+        &'slf self,
+        _ctx: &mut impl OutputContext<'out, Engine = E>,
+    ) -> &'out Self::Output
+    where
+        'slf: 'out,
+    {
+        self.output
+            .as_ref()
+            .expect("output called on a future that hasn't resolved yet")
+    }
+
+    fn debug_location(&self) -> Option<(&'static str, &'static Location<'static>)> {
+        Some(("from_future", self.location))
+    }
+}
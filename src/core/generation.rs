@@ -0,0 +1,24 @@
+use std::num::NonZeroU64;
+
+/// Identifies a stabilization of an engine.
+///
+/// Every stabilization gets a later generation than the ones before it, so generations can be
+/// compared to find out which of two stabilizations happened first.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Generation(NonZeroU64);
+
+impl Generation {
+    pub(crate) fn new() -> Generation {
+        Generation(NonZeroU64::new(1).unwrap())
+    }
+
+    /// Returns the generation as a number, which starts at 1 for a new engine.
+    pub fn get(self) -> u64 {
+        self.0.get()
+    }
+
+    pub(crate) fn increment(&mut self) {
+        let gen: u64 = u64::from(self.0).wrapping_add(1);
+        self.0 = NonZeroU64::new(gen).unwrap();
+    }
+}
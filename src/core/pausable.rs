@@ -0,0 +1,93 @@
+use std::panic::Location;
+
+use crate::core::{Anchor, AnchorCore, AnchorHandle, Engine, OutputContext, Poll, UpdateContext};
+
+/// A core anchor that outputs its input, except while `paused` is `true`.
+///
+/// While paused, it keeps outputting the value it had when it was paused, and unrequests its
+/// input so it stops being necessary on its behalf. Once resumed, it outputs its input's
+/// current value again.
+pub struct Pausable<O, E: Engine> {
+    pub(super) anchor: Anchor<O, E>,
+    pub(super) paused: Anchor<bool, E>,
+    pub(super) requested: bool,
+    // resumed, but the input's value wasn't taken yet because it was pending
+    pub(super) resuming: bool,
+    pub(super) output: Option<O>,
+    pub(super) location: &'static Location<'static>,
+}
+
+impl<O, E: Engine> Pausable<O, E> {
+    pub fn new(
+        anchor: Anchor<O, E>,
+        paused: Anchor<bool, E>,
+        location: &'static Location<'static>,
+    ) -> Self {
+        Self {
+            anchor,
+            paused,
+            requested: false,
+            resuming: false,
+            output: None,
+            location,
+        }
+    }
+}
+
+impl<O, E> AnchorCore<E> for Pausable<O, E>
+where
+    O: 'static + Clone,
+    E: Engine,
+{
+    type Output = O;
+
+    fn mark_dirty(&mut self, _edge: <E::AnchorHandle as AnchorHandle>::AnchorKey) {
+        // noop
+    }
+
+    fn poll_updated(&mut self, ctx: &mut impl UpdateContext<Engine = E>) -> Poll {
+        if ctx.request(&self.paused, true) == Poll::Pending {
+            return Poll::Pending;
+        }
+        // even a paused anchor needs some value to output
+        if *ctx.get(&self.paused) && self.output.is_some() {
+            if self.requested {
+                ctx.unrequest(&self.anchor);
+                self.requested = false;
+            }
+            return Poll::Unchanged;
+        }
+
+        // changes made while we weren't requesting our input may not show up as updates, so
+        // take its value after resuming either way
+        if !self.requested {
+            self.requested = true;
+            self.resuming = true;
+        }
+        match ctx.request(&self.anchor, true) {
+            Poll::Pending => Poll::Pending,
+            Poll::Unchanged if !self.resuming => Poll::Unchanged,
+            _ => {
+                self.resuming = false;
+                self.output = Some(ctx.get(&self.anchor).clone());
+                Poll::Updated
+            }
+        }
+    }
+
+    fn output<'slf, 'out>(
+        &'slf self,
+        _ctx: &mut impl OutputContext<'out, Engine = E>,
+    ) -> &'out Self::Output
+    where
+        'slf: 'out,
+    {
+        self.output
+            .as_ref()
+            .expect("output called on a `pausable` that wasn't polled yet")
+    }
+
+    fn debug_location(&self) -> Option<(&'static str, &'static Location<'static>)> {
+        Some(("pausable", self.location))
+    }
+}
@@ -0,0 +1,173 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::core::{Engine, Transaction, Variable};
+
+/// The values of all variables registered with an engine, keyed by the keys they were
+/// registered under.
+///
+/// Created by `Engine::snapshot`, and applied again by `Engine::restore`. It can be serialized
+/// with any serde format, so it may be stored and restored by a later process.
+#[derive(Clone, PartialEq, Default, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Snapshot {
+    values: BTreeMap<String, serde_json::Value>,
+}
+
+impl Snapshot {
+    /// Returns the value stored under `key`, in serialized form.
+    pub fn get(&self, key: &str) -> Option<&serde_json::Value> {
+        self.values.get(key)
+    }
+
+    /// Returns the keys of all values in the snapshot, in order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+}
+
+/// An error reported while taking or restoring a `Snapshot`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SnapshotError {
+    /// The snapshot contained a key no variable is registered under.
+    UnknownKey { key: String },
+
+    /// The value of the variable registered under `key` couldn't be serialized, or the
+    /// snapshot's value couldn't be deserialized into it.
+    Serde {
+        key: String,
+        source: serde_json::Error,
+    },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::UnknownKey { key } => {
+                write!(f, "no variable is registered under snapshot key `{}`", key)
+            }
+            SnapshotError::Serde { key, source } => {
+                write!(f, "failed to convert snapshot value `{}`: {}", key, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::UnknownKey { .. } => None,
+            SnapshotError::Serde { source, .. } => Some(source),
+        }
+    }
+}
+
+/// A variable registered with an engine's snapshots, with its value type erased.
+pub(crate) trait SnapshotEntry<E: Engine> {
+    fn save(&self) -> Result<serde_json::Value, serde_json::Error>;
+
+    /// Stages setting the variable to `value` on `transaction`, unless it's equal to the
+    /// current value.
+    fn stage(
+        &self,
+        value: &serde_json::Value,
+        transaction: &mut Transaction<E>,
+    ) -> Result<(), serde_json::Error>;
+}
+
+impl<T, E> SnapshotEntry<E> for Variable<T, E>
+where
+    T: 'static + Serialize + DeserializeOwned,
+    E: Engine,
+{
+    fn save(&self) -> Result<serde_json::Value, serde_json::Error> {
+        serde_json::to_value(&*self.get())
+    }
+
+    fn stage(
+        &self,
+        value: &serde_json::Value,
+        transaction: &mut Transaction<E>,
+    ) -> Result<(), serde_json::Error> {
+        if self.save()? != *value {
+            transaction.set(self, T::deserialize(value)?);
+        }
+        Ok(())
+    }
+}
+
+/// The variables registered with an engine's snapshots.
+///
+/// `S` is the trait object entries are stored as, which lets thread-safe engines require them
+/// to be `Send + Sync`.
+pub(crate) struct SnapshotRegistry<S: ?Sized> {
+    entries: BTreeMap<String, Box<S>>,
+}
+
+impl<S: ?Sized> Default for SnapshotRegistry<S> {
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl<S: ?Sized> SnapshotRegistry<S> {
+    pub(crate) fn register(&mut self, key: String, entry: Box<S>) {
+        if self.entries.contains_key(&key) {
+            panic!(
+                "a variable is already registered under snapshot key `{}`",
+                key
+            );
+        }
+        self.entries.insert(key, entry);
+    }
+
+    pub(crate) fn unregister(&mut self, key: &str) -> bool {
+        self.entries.remove(key).is_some()
+    }
+
+    pub(crate) fn snapshot<E>(&self) -> Result<Snapshot, SnapshotError>
+    where
+        S: SnapshotEntry<E>,
+        E: Engine,
+    {
+        let mut values = BTreeMap::new();
+        for (key, entry) in &self.entries {
+            let value = entry.save().map_err(|source| SnapshotError::Serde {
+                key: key.clone(),
+                source,
+            })?;
+            values.insert(key.clone(), value);
+        }
+        Ok(Snapshot { values })
+    }
+
+    /// Returns a transaction restoring all values in `snapshot` at once, which leaves variables
+    /// whose values didn't change untouched.
+    pub(crate) fn stage_restore<E>(
+        &self,
+        snapshot: &Snapshot,
+    ) -> Result<Transaction<E>, SnapshotError>
+    where
+        S: SnapshotEntry<E>,
+        E: Engine,
+    {
+        let mut transaction = Transaction::new();
+        for (key, value) in &snapshot.values {
+            let entry = self
+                .entries
+                .get(key)
+                .ok_or_else(|| SnapshotError::UnknownKey { key: key.clone() })?;
+            entry
+                .stage(value, &mut transaction)
+                .map_err(|source| SnapshotError::Serde {
+                    key: key.clone(),
+                    source,
+                })?;
+        }
+        Ok(transaction)
+    }
+}
@@ -8,10 +8,13 @@ use crate::core::{Anchor, AnchorCore, AnchorHandle, Engine, OutputContext, Poll,
 /// on some other incremental computation.
 ///
 /// The function `f` accepts inputs as references, and must return an owned `Anchor`.
-/// `f` will always be recalled any time any input value changes.
+/// `f` will always be recalled any time any input value changes. If the `Then` is scoped,
+/// Anchors created by `f` belong to it, and are invalidated once `f` is called again, on engines
+/// supporting scopes.
 pub struct Then<A, Out, F, E: Engine> {
     pub(super) anchors: A,
     pub(super) f: F,
+    pub(super) scoped: bool,
     pub(super) location: &'static Location<'static>,
     pub(super) f_anchor: Option<Anchor<Out, E>>,
     pub(super) lhs_stale: bool,
@@ -22,11 +25,21 @@ impl<A, Out, F, E: Engine> Then<A, Out, F, E> {
         Self {
             anchors,
             f,
+            scoped: false,
             location,
             f_anchor: None,
             lhs_stale: true,
         }
     }
+
+    /// Creates a `Then` that wraps each call of `f` in a scope, see
+    /// `UpdateContext::begin_scope`.
+    pub fn new_scoped(anchors: A, f: F, location: &'static Location<'static>) -> Self {
+        Self {
+            scoped: true,
+            ..Self::new(anchors, f, location)
+        }
+    }
 }
 
 macro_rules! impl_tuple_then {
@@ -82,7 +95,14 @@ macro_rules! impl_tuple_then {
                     self.lhs_stale = false;
 
                     if self.f_anchor.is_none() || found_updated {
+                        if self.scoped {
+                            // anchors built by the previous call are invalidated
+                            ctx.begin_scope();
+                        }
                         let new_anchor = (self.f)($(&ctx.get(&self.anchors.$num)),+);
+                        if self.scoped {
+                            ctx.end_scope();
+                        }
                         match self.f_anchor.as_ref() {
                             Some(outdated_anchor) if outdated_anchor != &new_anchor => {
                                 // changed, so unfollow old
@@ -109,7 +129,7 @@ macro_rules! impl_tuple_then {
             }
 
             fn debug_location(&self) -> Option<(&'static str, &'static Location<'static>)> {
-                Some(("then", self.location))
+                Some((if self.scoped { "then_scoped" } else { "then" }, self.location))
             }
         }
     }
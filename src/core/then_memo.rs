@@ -0,0 +1,142 @@
+use std::{collections::HashMap, hash::Hash, panic::Location};
+
+use crate::core::{Anchor, AnchorCore, AnchorHandle, Engine, OutputContext, Poll, UpdateContext};
+
+/// A core anchor that selects an Anchor to output like `Then`, but remembers the Anchors it
+/// built before.
+///
+/// `key_fn` maps the input value to a key, and `build_fn` builds the Anchor for a key the first
+/// time it's needed. Up to `capacity` Anchors are kept around, and the least recently used one
+/// is dropped to make room for another. Since kept Anchors stay alive, switching back to one
+/// whose inputs didn't change reuses its cached output.
+pub struct ThenMemo<In, K, FK, FB, Out, E: Engine> {
+    pub(super) anchor: Anchor<In, E>,
+    pub(super) key_fn: FK,
+    pub(super) build_fn: FB,
+    pub(super) capacity: usize,
+    pub(super) location: &'static Location<'static>,
+    // each branch along with the tick it was last used at
+    pub(super) branches: HashMap<K, (Anchor<Out, E>, u64)>,
+    pub(super) tick: u64,
+    pub(super) current: Option<Anchor<Out, E>>,
+    pub(super) input_stale: bool,
+    pub(super) switched: bool,
+}
+
+impl<In, K, FK, FB, Out, E: Engine> ThenMemo<In, K, FK, FB, Out, E> {
+    pub fn new(
+        anchor: Anchor<In, E>,
+        key_fn: FK,
+        build_fn: FB,
+        capacity: usize,
+        location: &'static Location<'static>,
+    ) -> Self {
+        assert!(capacity > 0, "attempted to remember zero branches");
+        Self {
+            anchor,
+            key_fn,
+            build_fn,
+            capacity,
+            location,
+            branches: HashMap::new(),
+            tick: 0,
+            current: None,
+            input_stale: true,
+            switched: false,
+        }
+    }
+}
+
+impl<In, K, FK, FB, Out, E> ThenMemo<In, K, FK, FB, Out, E>
+where
+    K: Eq + Hash,
+    FB: FnMut(&K) -> Anchor<Out, E>,
+    E: Engine,
+{
+    /// Returns the branch for `key`, building it and evicting the least recently used one if
+    /// necessary.
+    fn branch(&mut self, key: K) -> Anchor<Out, E> {
+        self.tick += 1;
+        if let Some((branch, last_used)) = self.branches.get_mut(&key) {
+            *last_used = self.tick;
+            return branch.clone();
+        }
+        if self.branches.len() >= self.capacity {
+            // ticks are unique, so this evicts exactly one branch
+            let least_recent = self
+                .branches
+                .values()
+                .map(|(_, last_used)| *last_used)
+                .min();
+            self.branches
+                .retain(|_, (_, last_used)| Some(*last_used) != least_recent);
+        }
+        let branch = (self.build_fn)(&key);
+        self.branches.insert(key, (branch.clone(), self.tick));
+        branch
+    }
+}
+
+impl<In, K, FK, FB, Out, E> AnchorCore<E> for ThenMemo<In, K, FK, FB, Out, E>
+where
+    In: 'static,
+    K: Eq + Hash,
+    FK: for<'any> FnMut(&'any In) -> K,
+    FB: FnMut(&K) -> Anchor<Out, E>,
+    Out: 'static,
+    E: Engine,
+{
+    type Output = Out;
+
+    fn mark_dirty(&mut self, edge: <E::AnchorHandle as AnchorHandle>::AnchorKey) {
+        if edge == self.anchor.key() {
+            self.input_stale = true;
+        }
+    }
+
+    fn poll_updated(&mut self, ctx: &mut impl UpdateContext<Engine = E>) -> Poll {
+        if self.current.is_none() || self.input_stale {
+            let input = ctx.request(&self.anchor, true);
+            if input == Poll::Pending {
+                return Poll::Pending;
+            }
+            self.input_stale = false;
+
+            if self.current.is_none() || input == Poll::Updated {
+                let key = (self.key_fn)(ctx.get(&self.anchor));
+                let branch = self.branch(key);
+                match &self.current {
+                    Some(current) if *current == branch => {}
+                    current => {
+                        if let Some(current) = current {
+                            ctx.unrequest(current);
+                        }
+                        self.current = Some(branch);
+                        self.switched = true;
+                    }
+                }
+            }
+        }
+
+        match ctx.request(self.current.as_ref().unwrap(), true) {
+            Poll::Pending => Poll::Pending,
+            // a branch we switched back to may not have changed since we last requested it
+            _ if std::mem::take(&mut self.switched) => Poll::Updated,
+            poll => poll,
+        }
+    }
+
+    fn output<'slf, 'out>(
+        &'slf self,
+        ctx: &mut impl OutputContext<'out, Engine = E>,
+    ) -> &'out Self::Output
+    where
+        'slf: 'out,
+    {
+        ctx.get(self.current.as_ref().unwrap())
+    }
+
+    fn debug_location(&self) -> Option<(&'static str, &'static Location<'static>)> {
+        Some(("then_memo", self.location))
+    }
+}
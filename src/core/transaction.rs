@@ -0,0 +1,72 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use crate::core::{Engine, Variable};
+
+/// A batch of variable writes, which are applied all at once when the transaction commits.
+///
+/// Created by `Runtime::transaction`. Until then, variables keep their old values and nothing
+/// is marked as dirty, so no stabilization can observe only part of the batch.
+pub struct Transaction<E: Engine + ?Sized> {
+    writes: Vec<Box<dyn FnOnce() -> Box<dyn Change>>>,
+    engine: PhantomData<fn() -> E>,
+}
+
+impl<E: Engine + ?Sized> Transaction<E> {
+    pub(crate) fn new() -> Self {
+        Self {
+            writes: vec![],
+            engine: PhantomData,
+        }
+    }
+
+    /// Stages setting `variable` to `value` once the transaction commits.
+    pub fn set<T>(&mut self, variable: &Variable<T, E>, value: T)
+    where
+        T: 'static,
+        E: Sized,
+    {
+        let variable = variable.clone();
+        self.writes.push(Box::new(move || {
+            let new = Arc::new(value);
+            let old = variable.replace(Arc::clone(&new));
+            Box::new(VarChange { variable, old, new })
+        }));
+    }
+
+    /// Discards all writes staged so far.
+    pub fn rollback(&mut self) {
+        self.writes.clear();
+    }
+
+    /// Returns whether no writes are staged.
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Applies all staged writes, in the order they were staged in.
+    pub(crate) fn commit(self) -> Vec<Box<dyn Change>> {
+        self.writes.into_iter().map(|write| write()).collect()
+    }
+}
+
+/// A variable write applied by a transaction, which can be reverted and applied again.
+pub(crate) trait Change {
+    fn undo(&self);
+    fn redo(&self);
+}
+
+struct VarChange<T, E: Engine> {
+    variable: Variable<T, E>,
+    old: Arc<T>,
+    new: Arc<T>,
+}
+
+impl<T, E: Engine> Change for VarChange<T, E> {
+    fn undo(&self) {
+        self.variable.replace(Arc::clone(&self.old));
+    }
+
+    fn redo(&self) {
+        self.variable.replace(Arc::clone(&self.new));
+    }
+}
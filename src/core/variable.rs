@@ -0,0 +1,327 @@
+use std::{
+    panic::Location,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, Weak},
+};
+
+use crate::{
+    core::{
+        AnchorCore, AnchorHandle, DirtyHandle as _, Engine, Mount, OutputContext, Poll,
+        UpdateContext,
+    },
+    Anchor,
+};
+
+/// A variable that exposes an anchor for its value, on any engine.
+///
+/// Engines usually re-export it with their engine filled in, e.g. as
+/// `single_threaded::Variable<T>`.
+///
+/// ```
+/// use anchors::{
+///     core::{Mount, Runtime, VarAnchor, Variable},
+///     single_threaded, sync,
+/// };
+///
+/// fn set_and_get<E: Runtime + Mount<VarAnchor<u32, E>>>(engine: &mut E) -> u32 {
+///     let variable = Variable::<u32, E>::new(1);
+///     variable.set(2);
+///     engine.get(&variable.watch())
+/// }
+///
+/// assert_eq!(set_and_get(&mut single_threaded::Engine::new()), 2);
+/// assert_eq!(set_and_get(&mut sync::Engine::new()), 2);
+/// ```
+pub struct Variable<T, E: Engine> {
+    inner: Arc<Mutex<VarShared<T, E>>>,
+    anchor: Anchor<T, E>,
+}
+
+impl<T, E: Engine> Clone for Variable<T, E> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            anchor: self.anchor.clone(),
+        }
+    }
+}
+
+impl<T, E> Variable<T, E>
+where
+    T: 'static,
+    E: Mount<VarAnchor<T, E>>,
+{
+    /// Creates a new variable
+    #[track_caller]
+    pub fn new(value: T) -> Self {
+        Self::from_arc(Arc::new(value))
+    }
+
+    /// Creates a new variable, whose value is shared with `value`'s other owners.
+    #[track_caller]
+    pub(crate) fn from_arc(value: Arc<T>) -> Self {
+        let inner = Arc::new(Mutex::new(VarShared {
+            dirty_handle: None,
+            value: Arc::clone(&value),
+            value_changed: true,
+            pending_updates: vec![],
+            remote_value: None,
+            on_write: None,
+        }));
+        Variable {
+            inner: Arc::clone(&inner),
+            anchor: E::mount(VarAnchor {
+                inner,
+                value: Some(value),
+                location: Location::caller(),
+            }),
+        }
+    }
+}
+
+impl<T, E: Engine> Variable<T, E> {
+    /// Updates the value inside the VarAnchor, and indicates to the recomputation graph that
+    /// the value has changed.
+    ///
+    /// This supersedes any value written remotely that hasn't been picked up yet.
+    pub fn set(&self, value: T) {
+        let mut inner = lock(&self.inner);
+        if let Some(remote_value) = &inner.remote_value {
+            lock(remote_value).take();
+        }
+        inner.set(value);
+    }
+
+    /// Sets the variable to `value` like `Variable::set`, unless it's equal to the current value.
+    ///
+    /// Returns whether the value was set.
+    pub fn set_if_changed(&self, value: T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.set_if_changed_by(value, T::eq)
+    }
+
+    /// Sets the variable to `value` like `Variable::set`, unless `eq` reports it's equal to the
+    /// current value.
+    ///
+    /// `eq` is called with the current value first. Returns whether the value was set.
+    pub fn set_if_changed_by(&self, value: T, eq: impl FnOnce(&T, &T) -> bool) -> bool {
+        let mut inner = lock(&self.inner);
+        inner.apply_remote();
+        inner.apply_pending_updates();
+        if eq(&inner.value, &value) {
+            return false;
+        }
+        inner.set(value);
+        true
+    }
+
+    /// Mutates the variable's value in place, and indicates to the recomputation graph that
+    /// the value has changed if `f` returns `true`.
+    ///
+    /// While the anchor outputs the current value, `f` is kept until the anchor is
+    /// recalculated, which then applies it in place. The value is only cloned if it's still
+    /// shared with a caller of `Variable::get`, or if `Variable::get` is called before the
+    /// anchor was recalculated. Since `f` may run during a stabilization, it shouldn't panic,
+    /// and if it returns `false`, anchors may or may not see what it mutated.
+    pub fn update(&self, f: impl 'static + Send + FnOnce(&mut T) -> bool)
+    where
+        T: Clone,
+    {
+        let mut inner = lock(&self.inner);
+        inner.apply_remote();
+        if let Some(value) = Arc::get_mut(&mut inner.value) {
+            if f(value) {
+                inner.changed();
+            }
+        } else if (Arc::strong_count(&inner.value) == 2 || !inner.pending_updates.is_empty())
+            && inner.on_write.is_none()
+        {
+            // the other reference is likely the anchor's, which may still be read until it's
+            // recalculated
+            inner
+                .pending_updates
+                .push(Box::new(|value| f(Arc::make_mut(value))));
+            inner.mark_dirty();
+            return;
+        } else if f(Arc::make_mut(&mut inner.value)) {
+            inner.changed();
+        }
+        inner.written();
+    }
+
+    /// Retrieves the last value set
+    pub fn get(&self) -> Arc<T> {
+        let mut inner = lock(&self.inner);
+        inner.apply_remote();
+        inner.apply_pending_updates();
+        Arc::clone(&inner.value)
+    }
+
+    pub fn watch(&self) -> Anchor<T, E> {
+        self.anchor.clone()
+    }
+
+    /// Sets the variable to `value` like `Variable::set`, returning the value it replaced.
+    pub(crate) fn replace(&self, value: Arc<T>) -> Arc<T> {
+        let mut inner = lock(&self.inner);
+        inner.apply_remote();
+        inner.apply_pending_updates();
+        let old = std::mem::replace(&mut inner.value, value);
+        inner.changed();
+        inner.written();
+        old
+    }
+
+    /// Calls `hook` with the value after each write from now on, replacing any hook set before.
+    ///
+    /// Updates are applied right away while there's a hook, instead of being kept until the
+    /// anchor is recalculated.
+    #[cfg(feature = "serde")]
+    pub(crate) fn on_write(&self, hook: Option<WriteHook<T>>) {
+        let mut inner = lock(&self.inner);
+        inner.apply_pending_updates();
+        inner.on_write = hook;
+    }
+
+    /// Returns the slot values written remotely are kept in until they're applied.
+    pub(crate) fn remote_value(&self) -> Arc<Mutex<Option<T>>> {
+        Arc::clone(
+            lock(&self.inner)
+                .remote_value
+                .get_or_insert_with(Default::default),
+        )
+    }
+
+    pub(crate) fn downgrade(&self) -> Weak<Mutex<VarShared<T, E>>> {
+        Arc::downgrade(&self.inner)
+    }
+}
+
+// Both are consistent at all times, so a panic while they were locked can be ignored.
+fn lock<T>(value: &Mutex<T>) -> MutexGuard<'_, T> {
+    value.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// mutates the value, cloning it first if it's shared
+type PendingUpdate<T> = Box<dyn FnOnce(&mut Arc<T>) -> bool + Send>;
+
+/// Called with a variable's value after each write, see `Variable::on_write`.
+pub(crate) type WriteHook<T> = Box<dyn Fn(&T) + Send>;
+
+pub(crate) struct VarShared<T, E: Engine> {
+    dirty_handle: Option<E::DirtyHandle>,
+    value: Arc<T>,
+    value_changed: bool,
+    // updates of `value` that wait for the anchor to be recalculated, oldest first
+    pending_updates: Vec<PendingUpdate<T>>,
+    remote_value: Option<Arc<Mutex<Option<T>>>>,
+    on_write: Option<WriteHook<T>>,
+}
+
+impl<T, E: Engine> VarShared<T, E> {
+    fn set(&mut self, value: T) {
+        self.pending_updates.clear();
+        self.value = Arc::new(value);
+        self.changed();
+        self.written();
+    }
+
+    fn written(&self) {
+        if let Some(hook) = &self.on_write {
+            hook(&self.value);
+        }
+    }
+
+    fn changed(&mut self) {
+        self.mark_dirty();
+        self.value_changed = true;
+    }
+
+    fn mark_dirty(&mut self) {
+        if let Some(waker) = &self.dirty_handle {
+            waker.mark_dirty();
+        }
+    }
+
+    /// Applies the pending updates to `value`, cloning it if it's shared.
+    fn apply_pending_updates(&mut self) {
+        if self.pending_updates.is_empty() {
+            return;
+        }
+        for update in std::mem::take(&mut self.pending_updates) {
+            if update(&mut self.value) {
+                self.value_changed = true;
+            }
+        }
+    }
+
+    /// Applies the most recent remote write, if there's one that wasn't superseded yet.
+    pub(crate) fn apply_remote(&mut self) {
+        let value = match &self.remote_value {
+            Some(remote_value) => lock(remote_value).take(),
+            None => None,
+        };
+        if let Some(value) = value {
+            self.set(value);
+        }
+    }
+}
+
+/// A core anchor for values that are mutated by calling a setter function from outside of the
+/// Anchors recomputation graph.
+pub struct VarAnchor<T, E: Engine> {
+    inner: Arc<Mutex<VarShared<T, E>>>,
+    // only `None` while applying pending updates
+    value: Option<Arc<T>>,
+    location: &'static Location<'static>,
+}
+
+impl<T, E> AnchorCore<E> for VarAnchor<T, E>
+where
+    T: 'static,
+    E: Engine,
+{
+    type Output = T;
+
+    fn mark_dirty(&mut self, _edge: <E::AnchorHandle as AnchorHandle>::AnchorKey) {
+        panic!("attempt to mark a variable's non-existent inputs as as dirty")
+    }
+
+    fn poll_updated(&mut self, ctx: &mut impl UpdateContext<Engine = E>) -> Poll {
+        let mut inner = lock(&self.inner);
+        let first_update = inner.dirty_handle.is_none();
+        if first_update {
+            inner.dirty_handle = Some(ctx.dirty_handle());
+        }
+        if !inner.pending_updates.is_empty() {
+            // nothing reads the output while we're polled, so we can drop it and let the
+            // updates mutate the value in place
+            self.value = None;
+            inner.apply_pending_updates();
+            self.value = Some(Arc::clone(&inner.value));
+        }
+        let res = if inner.value_changed {
+            self.value = Some(Arc::clone(&inner.value));
+            Poll::Updated
+        } else {
+            Poll::Unchanged
+        };
+        inner.value_changed = false;
+        res
+    }
+
+    fn output<'slf, 'out>(
+        &'slf self,
+        _ctx: &mut impl OutputContext<'out, Engine = E>,
+    ) -> &'out Self::Output
+    where
+        'slf: 'out,
+    {
+        self.value.as_ref().unwrap()
+    }
+
+    fn debug_location(&self) -> Option<(&'static str, &'static Location<'static>)> {
+        Some(("Variable", self.location))
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+
+/// An error reported by an engine's `try_*` methods.
+///
+/// Anchors are named by their debug location, e.g. `src/main.rs:12:5 (map)`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An anchor was used with an engine other than the one it was mounted on.
+    ///
+    /// `requested_by` names the anchor that requested it, unless it was passed to the engine
+    /// directly.
+    ForeignAnchor { requested_by: Option<String> },
+
+    /// Requesting an anchor would have made it depend on itself.
+    ///
+    /// Each anchor in `path` depends on the next one, and the last one on the first.
+    Cycle { path: Vec<String> },
+
+    /// Requesting an anchor would have made the graph higher than the maximum height the engine
+    /// was created with.
+    HeightExceeded { anchor: String, max_height: usize },
+
+    /// A panic during an earlier recalculation left the engine in an inconsistent state.
+    ///
+    /// Poisoning is permanent: the engine can't tell which of its nodes the panic left behind
+    /// half-updated, so it keeps reporting this error, and has to be replaced by a new one.
+    Poisoned,
+
+    /// An anchor was used after the `then` that created it reran, which invalidated it.
+    Invalidated { anchor: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ForeignAnchor {
+                requested_by: Some(anchor),
+            } => write!(f, "{} requested an anchor from another engine", anchor),
+            Error::ForeignAnchor { requested_by: None } => {
+                write!(f, "attempted to use an anchor from another engine")
+            }
+            Error::Cycle { path } => {
+                write!(f, "loop detected in anchors, each depending on the next: ")?;
+                for anchor in path {
+                    write!(f, "{} -> ", anchor)?;
+                }
+                match path.first() {
+                    Some(first) => write!(f, "{}", first),
+                    None => Ok(()),
+                }
+            }
+            Error::HeightExceeded { anchor, max_height } => write!(
+                f,
+                "{} would exceed the engine's maximum height of {}",
+                anchor, max_height
+            ),
+            Error::Poisoned => write!(
+                f,
+                "engine was poisoned by a panic during an earlier recalculation"
+            ),
+            Error::Invalidated { anchor } => write!(
+                f,
+                "{} was created by a `then` that reran since, so it can't be used anymore",
+                anchor
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /// Adds `anchor` to the path of a cycle found while raising the heights of `anchor`'s
+    /// parents.
+    pub(crate) fn via(self, anchor: impl FnOnce() -> String) -> Self {
+        match self {
+            Error::Cycle { mut path } => {
+                path.push(anchor());
+                Error::Cycle { path }
+            }
+            err => err,
+        }
+    }
+}
@@ -1,16 +1,20 @@
 mod collections;
 pub mod core;
 pub mod single_threaded;
+pub mod sync;
 
 mod anchor;
 mod arena;
+mod error;
+#[cfg(test)]
+mod test_util;
 
 /// The crate's prelude.
 pub mod prelude {
     pub use crate::core::{
-        AnchorCore, AnchorHandle, DirtyHandle, Engine, OutputContext, UpdateContext,
+        AnchorCore, AnchorHandle, DirtyHandle, Engine, OutputContext, Runtime, UpdateContext,
     };
     pub use crate::{Anchor, MultiAnchor};
 }
 
-pub use self::anchor::*;
+pub use self::{anchor::*, error::*};
@@ -3,55 +3,53 @@
 //! It's a single threaded engine capable of both [Adapton](https://crates.io/crates/adapton)-style pull updates
 //! and — if `mark_observed` and `mark_unobserved` are used, [Incremental](https://crates.io/crates/incremental)-style push updates.
 
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    rc::{Rc, Weak},
+};
 
 mod anchor;
 mod anchor_handle;
+mod clock;
 mod constant;
 mod context;
 mod context_mut;
+mod dot;
 mod engine;
-mod generation;
+mod fork;
 mod graph;
 mod graph_guard;
+mod history;
 mod node;
+mod node_debug_row;
 mod node_guard;
 mod node_iterator;
 mod node_key;
 mod node_ptrs;
+mod observer;
+mod query;
+#[cfg(feature = "serde")]
+mod recorder;
+mod remote;
 mod variable;
 
-pub use self::{anchor::*, anchor_handle::*, constant::*, engine::*, variable::*};
-
-use self::{
-    context::*, context_mut::*, generation::*, graph::*, graph_guard::*, node::*, node_guard::*,
-    node_iterator::*, node_key::*, node_ptrs::*,
+pub use self::{
+    anchor::*, anchor_handle::*, clock::*, constant::*, engine::*, fork::*, graph::RecalcState,
+    node_debug_row::*, observer::*, query::*, variable::*,
 };
 
-thread_local! {
-    static DEFAULT_MOUNTER: RefCell<Option<Mounter>> = const { RefCell::new(None) };
-}
+#[cfg(feature = "serde")]
+pub use self::recorder::*;
 
-/// Indicates whether the node is a part of some observed calculation.
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
-pub enum ObservedState {
-    /// The node has been marked as observed directly via `mark_observed`.
-    Observed,
+pub use crate::core::{BeforeOrAfter, Generation, ObservedState};
 
-    /// The node is not marked as observed directly.
-    /// However, the node has some descendent that is Observed, and this node has
-    /// been recalculated since that descendent become Observed.
-    Necessary,
-
-    /// The node is not marked as observed directly.
-    /// Additionally, this node either has no Observed descendent, or the chain linking
-    /// this node to that Observed descendent has not been recalculated since that
-    /// descendent become observed.
-    Unnecessary,
-}
+use self::{
+    context::*, context_mut::*, graph::*, graph_guard::*, history::*, node::*, node_guard::*,
+    node_iterator::*, node_key::*, node_ptrs::*, remote::*,
+};
 
-struct Mounter {
-    graph: Rc<Graph>,
+thread_local! {
+    static DEFAULT_MOUNTER: RefCell<Option<Rc<Graph>>> = const { RefCell::new(None) };
 }
 
 // skip_self = true indicates output has *definitely* changed, but node has been recalculated
@@ -61,12 +59,12 @@ fn mark_dirty<'a>(graph: GraphGuard<'a>, node: NodeGuard<'a>, skip_self: bool) {
         let parents = node.drain_clean_parents();
         for parent in parents {
             // TODO still calling dirty twice on observed relationships
-            parent
-                .anchor
-                .borrow_mut()
-                .as_mut()
-                .unwrap()
-                .mark_dirty(AnchorKey::new(node.key()));
+            // invalidated parents aren't recalculated anymore
+            if let Some(v) = parent.anchor.borrow_mut().as_mut() {
+                v.mark_dirty(AnchorKey::new(node.key()));
+            } else {
+                continue;
+            }
             mark_dirty0(graph, parent);
         }
     } else {
@@ -78,7 +76,10 @@ fn mark_dirty0<'a>(graph: GraphGuard<'a>, next: NodeGuard<'a>) {
     let anchor_key = AnchorKey::new(next.key());
     if Engine::check_observed_raw(next) != ObservedState::Unnecessary {
         graph.queue_recalc(next);
-    } else if graph::recalc_state(next) == RecalcState::Ready {
+    } else if matches!(
+        graph::recalc_state(next),
+        RecalcState::Ready | RecalcState::Waiting
+    ) {
         graph::needs_recalc(next);
         let parents = next.drain_clean_parents();
         for parent in parents {
@@ -103,5 +104,17 @@ impl crate::core::DirtyHandle for DirtyHandle {
     }
 }
 
+impl RemoteTarget for Weak<DirtyHandle> {
+    fn apply_remote(&self) {
+        if let Some(dirty_handle) = self.upgrade() {
+            crate::core::DirtyHandle::mark_dirty(&*dirty_handle);
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        self.strong_count() > 0
+    }
+}
+
 #[cfg(test)]
 mod tests;
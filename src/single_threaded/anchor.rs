@@ -1,8 +1,8 @@
-use std::{any::Any, panic::Location};
+use std::any::Any;
 
-use crate::core::{AnchorCore, Poll};
+use crate::core::{AnchorCore, AnchorDebugInfo, Poll};
 
-use super::{AnchorKey, Constant, Engine, EngineContext, EngineContextMut};
+use super::{AnchorKey, Engine, EngineContext, EngineContextMut};
 
 /// The main struct of the Anchors library.
 ///
@@ -12,17 +12,6 @@ use super::{AnchorKey, Constant, Engine, EngineContext, EngineContextMut};
 /// instead call functions like `Variable::new`, `Constant::new` and `MultiAnchor::map` to create them.
 pub type Anchor<T> = crate::Anchor<T, Engine>;
 
-impl<T> Anchor<T> {
-    /// A constant value's anchor without a corresponding `Constant`.
-    #[track_caller]
-    pub fn constant(value: T) -> Self
-    where
-        T: 'static,
-    {
-        Constant::new(value).into_anchor()
-    }
-}
-
 pub(super) trait GenericAnchor {
     fn mark_dirty(&mut self, child_key: AnchorKey);
 
@@ -61,18 +50,3 @@ where
         }
     }
 }
-
-#[derive(Copy, Clone, Debug)]
-pub(super) struct AnchorDebugInfo {
-    pub(super) location: Option<(&'static str, &'static Location<'static>)>,
-    pub(super) type_info: &'static str,
-}
-
-impl std::fmt::Display for AnchorDebugInfo {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.location {
-            Some((name, location)) => write!(f, "{location} ({name})"),
-            None => write!(f, "{}", self.type_info),
-        }
-    }
-}
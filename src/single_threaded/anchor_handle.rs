@@ -1,6 +1,6 @@
 use std::{cell::Cell, rc::Rc};
 
-use super::{free, NodeKey};
+use super::{free, Graph, NodeGuard, NodeKey};
 
 /// A key uniquely identifying a handle within a computational graph.
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
@@ -28,6 +28,19 @@ impl AnchorHandle {
             still_alive,
         }
     }
+
+    /// Returns the graph this anchor is mounted on, unless it was dropped already.
+    pub(super) fn graph(&self) -> Option<&Graph> {
+        Some(unsafe { &*self.node()?.ptrs.graph })
+    }
+
+    /// Returns this anchor's node, unless its graph was dropped already.
+    pub(super) fn node(&self) -> Option<NodeGuard<'_>> {
+        if !self.still_alive.get() {
+            return None;
+        }
+        Some(NodeGuard(unsafe { self.node_key.ptr.lookup_unchecked() }))
+    }
 }
 
 impl Clone for AnchorHandle {
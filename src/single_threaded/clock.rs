@@ -0,0 +1,4 @@
+use super::Engine;
+
+/// A virtual clock that exposes anchors for points in time.
+pub type Clock = crate::core::Clock<Engine>;
@@ -1,4 +1,9 @@
-use crate::core::{Poll, UpdateContext};
+use std::{rc::Rc, task::Waker};
+
+use crate::{
+    core::{Poll, UpdateContext},
+    Error,
+};
 
 use super::{
     Anchor, DirtyHandle, Engine, EngineContext, GraphGuard, NodeGuard, ObservedState, RecalcState,
@@ -9,6 +14,7 @@ pub(super) struct EngineContextMut<'eng, 'gg> {
     graph: GraphGuard<'gg>,
     node: NodeGuard<'gg>,
     pending_on_anchor_get: bool,
+    error: Option<Error>,
 }
 
 impl<'eng, 'gg> EngineContextMut<'eng, 'gg> {
@@ -18,12 +24,24 @@ impl<'eng, 'gg> EngineContextMut<'eng, 'gg> {
             graph,
             node,
             pending_on_anchor_get: false,
+            error: None,
         }
     }
 
     pub(super) fn pending_on_anchor_get(&self) -> bool {
         self.pending_on_anchor_get
     }
+
+    /// Returns the first error encountered while polling, if any.
+    pub(super) fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+
+    /// Records `error`, and reports the requested anchor as pending so the polled node bails.
+    fn fail(&mut self, error: Error) -> Poll {
+        self.error.get_or_insert(error);
+        Poll::Pending
+    }
 }
 
 impl UpdateContext for EngineContextMut<'_, '_> {
@@ -55,20 +73,40 @@ impl UpdateContext for EngineContextMut<'_, '_> {
     where
         O: 'static,
     {
-        let child = self.graph.get(anchor.key().node_key).unwrap();
+        let child = match self.graph.get(anchor.key().node_key) {
+            Some(child) => child,
+            None => {
+                return self.fail(Error::ForeignAnchor {
+                    requested_by: Some(self.node.debug_info.get().to_string()),
+                })
+            }
+        };
+        if child.invalidated.get() {
+            return self.fail(Error::Invalidated {
+                anchor: child.debug_info.get().to_string(),
+            });
+        }
         let height_already_increased = match super::graph::ensure_height_increases(child, self.node)
         {
             Ok(v) => v,
-            Err(()) => {
-                panic!("loop detected in anchors!\n");
-            }
+            Err(err) => return self.fail(err),
         };
 
         let self_is_necessary = Engine::check_observed_raw(self.node) != ObservedState::Unnecessary;
 
-        if super::graph::recalc_state(child) != RecalcState::Ready {
+        if super::graph::recalc_state(child) == RecalcState::Waiting {
+            // recalculating won't help until the child is woken up; once it finished, it
+            // marks us as dirty like any other clean parent
+            child.add_clean_parent(self.node);
+            if necessary && self_is_necessary {
+                self.node.add_necessary_child(child);
+            }
+            Poll::Pending
+        } else if super::graph::recalc_state(child) != RecalcState::Ready {
             self.pending_on_anchor_get = true;
             self.graph.queue_recalc(child);
+            // not clean yet, but tracking the edge lets height updates find cycles through it
+            child.add_clean_parent(self.node);
             if necessary && self_is_necessary {
                 self.node.add_necessary_child(child);
             }
@@ -100,4 +138,32 @@ impl UpdateContext for EngineContextMut<'_, '_> {
     fn dirty_handle(&mut self) -> DirtyHandle {
         self.engine.dirty_handle_for_node(self.node.key())
     }
+
+    fn waker(&mut self) -> Waker {
+        if let Some((_, waker)) = &*self.node.waker.borrow() {
+            return waker.clone();
+        }
+        let dirty_handle = Rc::new(self.dirty_handle());
+        let waker = self.engine.remote_waker(&dirty_handle);
+        *self.node.waker.borrow_mut() = Some((dirty_handle, waker.clone()));
+        waker
+    }
+
+    fn begin_scope(&mut self) {
+        let scoped = self.node.scoped.take();
+        for handle in &scoped {
+            if let Some(node) = handle.node() {
+                super::graph::invalidate(node);
+            }
+        }
+        drop(scoped);
+        let graph = unsafe { &*self.node.ptrs.graph };
+        graph.open_scopes.borrow_mut().push(vec![]);
+    }
+
+    fn end_scope(&mut self) {
+        let graph = unsafe { &*self.node.ptrs.graph };
+        let scope = graph.open_scopes.borrow_mut().pop();
+        *self.node.scoped.borrow_mut() = scope.unwrap_or_default();
+    }
 }
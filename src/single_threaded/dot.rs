@@ -0,0 +1,96 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+};
+
+use super::{graph, Engine, GraphGuard, NodeGuard, NodeKey, ObservedState, RecalcState};
+
+/// Renders the live nodes of `graph` in Graphviz's DOT language.
+///
+/// If `root` is given, only nodes it (transitively) depends on are included.
+pub(super) fn to_dot<'a>(graph: GraphGuard<'a>, root: Option<NodeGuard<'a>>) -> String {
+    let nodes = graph.live_nodes();
+
+    // edges point from inputs to the anchors using them
+    let mut edges: Vec<(NodeGuard<'_>, NodeGuard<'_>, &str)> = vec![];
+    for &node in &nodes {
+        for parent in node.clean_parents() {
+            edges.push((node, parent, "solid"));
+        }
+        for child in node.necessary_children() {
+            edges.push((child, node, "dashed"));
+        }
+    }
+
+    let included: Option<HashSet<NodeKey>> = root.map(|root| {
+        let mut inputs: HashMap<NodeKey, Vec<NodeGuard<'_>>> = HashMap::new();
+        for &(input, user, _) in &edges {
+            inputs.entry(user.key()).or_default().push(input);
+        }
+        let mut included = HashSet::new();
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            if included.insert(node.key()) {
+                stack.extend(inputs.get(&node.key()).into_iter().flatten().copied());
+            }
+        }
+        included
+    });
+    let is_included = |node: NodeGuard<'_>| {
+        included
+            .as_ref()
+            .map_or(true, |set| set.contains(&node.key()))
+    };
+
+    let ids: HashMap<NodeKey, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(id, node)| (node.key(), id))
+        .collect();
+
+    let mut dot = String::new();
+    dot += "digraph anchors {\n";
+    dot += "    node [shape=box, style=filled];\n";
+    for (id, &node) in nodes.iter().enumerate() {
+        if !is_included(node) {
+            continue;
+        }
+        let fill = match Engine::check_observed_raw(node) {
+            ObservedState::Observed => "gold",
+            ObservedState::Necessary => "lightblue",
+            ObservedState::Unnecessary => "white",
+        };
+        let border = match graph::recalc_state(node) {
+            RecalcState::Ready => "black",
+            RecalcState::Needed => "red",
+            RecalcState::Pending => "orange",
+            RecalcState::Waiting => "purple",
+        };
+        let label = node
+            .debug_info
+            .get()
+            .to_string()
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"");
+        let _ = writeln!(
+            dot,
+            "    n{} [label=\"{}\\nheight {}\", fillcolor={}, color={}];",
+            id,
+            label,
+            graph::height(node),
+            fill,
+            border
+        );
+    }
+    for (input, user, style) in edges {
+        if !is_included(input) || !is_included(user) {
+            continue;
+        }
+        // parents may have been freed without their children noticing yet
+        if let (Some(input), Some(user)) = (ids.get(&input.key()), ids.get(&user.key())) {
+            let _ = writeln!(dot, "    n{} -> n{} [style={}];", input, user, style);
+        }
+    }
+    dot += "}\n";
+    dot
+}
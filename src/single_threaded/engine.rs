@@ -1,20 +1,63 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    panic::Location,
+    rc::{Rc, Weak},
+    task::Waker,
+    time::{Duration, Instant},
+};
 
-use crate::core::{AnchorCore, Poll};
+use crate::{
+    core::{AnchorCore, Mount, Poll, Transaction},
+    Error,
+};
 
 use super::{
-    Anchor, AnchorHandle, DirtyHandle, EngineContext, EngineContextMut, Generation, GenericAnchor,
-    Graph, GraphGuard, Mounter, NodeGuard, NodeKey, ObservedState, RecalcState, DEFAULT_MOUNTER,
+    Anchor, AnchorHandle, Clock, DirtyHandle, EngineContext, EngineContextMut, Fork, ForkBuilder,
+    Generation, GenericAnchor, Graph, GraphGuard, History, NodeDebugRow, NodeGuard, NodeKey,
+    ObservedState, Observer, ObserverShared, ObserverTarget, Queries, Query, RecalcState,
+    DEFAULT_MOUNTER,
 };
 
+#[cfg(feature = "serde")]
+use super::Recorder;
+
 /// An engine for single-threaded execution of a computation graph.
+///
+/// Once enabled with `Engine::enable_history`, its undo history records the writes made
+/// through the engine, by `Engine::transaction` and `Engine::restore`. Writes made directly on
+/// a variable, like `Variable::set`, aren't recorded, since variables don't know which engine
+/// they're used with; stage them on a transaction to make them undoable.
 pub struct Engine {
     // TODO store Nodes on heap directly?? maybe try for Rc<RefCell<SlotMap>> now
     graph: Rc<Graph>,
     dirty_marks: Rc<RefCell<Vec<NodeKey>>>,
 
+    // set while stabilizing; if it's still set afterwards, a recalculation panicked, which
+    // poisons the engine for good
+    stabilizing: Cell<bool>,
+
     // tracks the current stabilization generation; incremented on every stabilize
     generation: Generation,
+
+    // notified after every stabilization, in the order they were created in
+    observers: Vec<Weak<dyn ObserverTarget>>,
+
+    // created the first time it's asked for
+    clock: RefCell<Option<Clock>>,
+
+    // recorded transactions, if enabled
+    history: Option<History>,
+
+    // logs how the engine is driven, if attached
+    #[cfg(feature = "serde")]
+    recorder: Option<Recorder>,
+
+    // memoized query anchors, by query and key
+    queries: Rc<RefCell<Queries>>,
+
+    // variables included in snapshots, by key
+    #[cfg(feature = "serde")]
+    snapshots: crate::core::SnapshotRegistry<dyn crate::core::SnapshotEntry<Engine>>,
 }
 
 impl Default for Engine {
@@ -26,47 +69,207 @@ impl Default for Engine {
 impl crate::core::Engine for Engine {
     type AnchorHandle = AnchorHandle;
     type DirtyHandle = DirtyHandle;
+}
+
+impl crate::core::ScopedEngine for Engine {}
+
+impl crate::core::Runtime for Engine {
+    fn try_mark_observed<O>(&mut self, anchor: &Anchor<O>) -> Result<(), Error>
+    where
+        O: 'static,
+    {
+        Engine::try_mark_observed(self, anchor)
+    }
 
-    fn mount<I>(inner: I) -> Anchor<I::Output>
+    fn try_mark_unobserved<O>(&mut self, anchor: &Anchor<O>) -> Result<(), Error>
     where
-        I: 'static + AnchorCore<Self>,
+        O: 'static,
     {
+        Engine::try_mark_unobserved(self, anchor)
+    }
+
+    fn check_observed<O>(&self, anchor: &Anchor<O>) -> ObservedState {
+        Engine::check_observed(self, anchor)
+    }
+
+    fn try_get<O>(&mut self, anchor: &Anchor<O>) -> Result<O, Error>
+    where
+        O: 'static + Clone,
+    {
+        Engine::try_get(self, anchor)
+    }
+
+    fn try_stabilize(&mut self) -> Result<(), Error> {
+        Engine::try_stabilize(self)
+    }
+
+    fn transaction<R>(&mut self, f: impl FnOnce(&mut Transaction<Self>) -> R) -> R {
+        Engine::transaction(self, f)
+    }
+}
+
+impl<I> Mount<I> for Engine
+where
+    I: 'static + AnchorCore<Self>,
+{
+    fn mount(inner: I) -> Anchor<I::Output> {
         DEFAULT_MOUNTER.with(|default_mounter| {
             let mut borrow = default_mounter.borrow_mut();
-            let this = borrow
+            let graph = borrow
                 .as_mut()
                 .expect("no engine was initialized. did you call `Engine::new()`?");
             let debug_info = inner.debug_info();
-            let handle = this.graph.insert(Box::new(inner), debug_info);
+            let handle = graph.insert(Box::new(inner), debug_info);
             Anchor::new_from_core(handle)
         })
     }
 }
 
 impl Engine {
-    /// Creates a new Engine with maximum height 256.
+    /// Creates a new Engine, whose graph may grow arbitrarily high.
+    ///
+    /// Anchors created from now on will be mounted onto this engine. Use `Engine::enter` to
+    /// switch between several engines.
     pub fn new() -> Self {
-        Self::new_with_max_height(256)
+        Self::new_with_graph(Graph::new(None))
     }
 
-    /// Creates a new Engine with a custom maximum height.
+    /// Creates a new Engine, which refuses to raise any anchor to `max_height` or above.
+    ///
+    /// Exceeding it is reported as `Error::HeightExceeded`.
     pub fn new_with_max_height(max_height: usize) -> Self {
-        let graph = Rc::new(Graph::new(max_height));
-        let mounter = Mounter {
-            graph: Rc::clone(&graph),
-        };
-        DEFAULT_MOUNTER.with(|v| *v.borrow_mut() = Some(mounter));
-        Self {
-            graph,
+        Self::new_with_graph(Graph::new(Some(max_height)))
+    }
+
+    fn new_with_graph(graph: Graph) -> Self {
+        let engine = Self {
+            graph: Rc::new(graph),
             dirty_marks: Default::default(),
+            stabilizing: Cell::new(false),
             generation: Generation::new(),
-        }
+            observers: vec![],
+            clock: RefCell::new(None),
+            history: None,
+            #[cfg(feature = "serde")]
+            recorder: None,
+            queries: Default::default(),
+            #[cfg(feature = "serde")]
+            snapshots: Default::default(),
+        };
+        DEFAULT_MOUNTER.with(|v| *v.borrow_mut() = Some(Rc::clone(&engine.graph)));
+        engine
+    }
+
+    /// Runs `f` with this as the engine new anchors are mounted onto, making the previously
+    /// current engine current again afterwards.
+    ///
+    /// This allows using several engines side by side on one thread.
+    pub fn enter<R>(&self, f: impl FnOnce() -> R) -> R {
+        let _guard = self.enter_guard();
+        f()
+    }
+
+    /// Makes this the engine new anchors are mounted onto, until the returned guard is
+    /// dropped.
+    pub fn enter_guard(&self) -> EnterGuard {
+        let previous = DEFAULT_MOUNTER.with(|v| v.replace(Some(Rc::clone(&self.graph))));
+        EnterGuard { previous }
+    }
+
+    /// Returns a speculative `Fork` of this engine, whose graph is mounted by `build`.
+    ///
+    /// `build` copies the engine's variables it needs with `ForkBuilder::variable`, and mounts
+    /// the anchors depending on them again. The engine current before stays current.
+    pub fn fork<G>(&self, build: impl FnOnce(&mut ForkBuilder) -> G) -> Fork<G> {
+        // creating an engine makes it current, so restore whichever one was current before
+        let fork_engine = {
+            let _guard = self.enter_guard();
+            match self.graph.max_height {
+                Some(max_height) => Self::new_with_max_height(max_height),
+                None => Self::new(),
+            }
+        };
+        Fork::new(fork_engine, build)
+    }
+
+    /// Returns the engine's virtual clock, creating it the first time it's asked for.
+    ///
+    /// Its time starts out as the moment it was created, and only moves when it's advanced.
+    pub fn clock(&self) -> Clock {
+        self.clock
+            .borrow_mut()
+            .get_or_insert_with(|| self.enter(|| Clock::new(Instant::now())))
+            .clone()
     }
 
     pub(super) fn with<F: for<'any> FnOnce(GraphGuard<'any>) -> R, R>(&self, f: F) -> R {
         self.graph.with(f)
     }
 
+    /// Runs `f` with a `Transaction`, whose staged variable writes are applied all at once
+    /// after `f` returned, unless they were rolled back.
+    ///
+    /// See `Runtime::transaction`. While history is enabled, each transaction that wrote any
+    /// variables is recorded as one batch, which `Engine::undo` reverts.
+    pub fn transaction<R>(&mut self, f: impl FnOnce(&mut Transaction<Self>) -> R) -> R {
+        let mut transaction = Transaction::new();
+        let res = f(&mut transaction);
+        self.commit(transaction);
+        res
+    }
+
+    /// Applies the writes staged on `transaction`, recording them as one batch while history
+    /// is enabled.
+    fn commit(&mut self, transaction: Transaction<Self>) {
+        let batch = transaction.commit();
+        if let Some(history) = &mut self.history {
+            history.record(batch);
+        }
+    }
+
+    /// Starts recording the variable writes of transactions and restored snapshots, keeping
+    /// the latest `depth` batches for `Engine::undo`.
+    ///
+    /// If history was enabled already, this only changes its depth. Writes made directly on
+    /// variables aren't recorded, see `Engine`.
+    pub fn enable_history(&mut self, depth: usize) {
+        match &mut self.history {
+            Some(history) => history.set_depth(depth),
+            None => self.history = Some(History::new(depth)),
+        }
+    }
+
+    /// Stops recording transactions, and forgets all recorded batches.
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Sets the variables written by the latest recorded batch back to the values they had
+    /// before it, all at once.
+    ///
+    /// Returns whether there was a batch to undo.
+    pub fn undo(&mut self) -> bool {
+        self.history.as_mut().map_or(false, History::undo)
+    }
+
+    /// Applies the most recently undone batch again, unless another transaction was recorded
+    /// since.
+    ///
+    /// Returns whether there was a batch to redo.
+    pub fn redo(&mut self) -> bool {
+        self.history.as_mut().map_or(false, History::redo)
+    }
+
+    /// Returns whether `Engine::undo` would undo a batch.
+    pub fn can_undo(&self) -> bool {
+        self.history.as_ref().map_or(false, History::can_undo)
+    }
+
+    /// Returns whether `Engine::redo` would redo a batch.
+    pub fn can_redo(&self) -> bool {
+        self.history.as_ref().map_or(false, History::can_redo)
+    }
+
     /// Marks an Anchor as observed. All observed nodes will always be brought up-to-date
     /// when *any* Anchor in the graph is retrieved. If you get an output value fairly
     /// often, it's best to mark it as Observed so that Anchors can calculate its
@@ -75,13 +278,68 @@ impl Engine {
     where
         O: 'static,
     {
+        self.try_mark_observed(anchor)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Marks an Anchor as observed like `Engine::mark_observed`, but reports misuse as an
+    /// error instead of panicking.
+    pub fn try_mark_observed<O>(&mut self, anchor: &Anchor<O>) -> Result<(), Error>
+    where
+        O: 'static,
+    {
+        self.check_poisoned()?;
         self.with(|graph| {
-            let node = graph.get(anchor.key().node_key).unwrap();
+            let node = graph
+                .get(anchor.key().node_key)
+                .ok_or(Error::ForeignAnchor { requested_by: None })?;
             node.observed.set(true);
             if super::graph::recalc_state(node) != RecalcState::Ready {
                 graph.queue_recalc(node);
             }
-        })
+            Ok(())
+        })?;
+        #[cfg(feature = "serde")]
+        if let Some(recorder) = &self.recorder {
+            recorder.record_observed(anchor.key(), true);
+        }
+        Ok(())
+    }
+
+    /// Marks an Anchor as observed for as long as the returned `Observer` is alive, bringing
+    /// it up-to-date.
+    ///
+    /// The observer exposes the anchor's latest value, and calls its `on_update` callbacks
+    /// whenever a stabilization changed it.
+    pub fn observe<O>(&mut self, anchor: &Anchor<O>) -> Observer<O>
+    where
+        O: 'static + Clone,
+    {
+        self.try_observe(anchor)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Observes an Anchor like `Engine::observe`, but reports misuse as an error instead of
+    /// panicking.
+    pub fn try_observe<O>(&mut self, anchor: &Anchor<O>) -> Result<Observer<O>, Error>
+    where
+        O: 'static + Clone,
+    {
+        let node_key = anchor.key().node_key;
+        let value = self.try_get(anchor)?;
+        let last_update = self.with(|graph| {
+            let node = graph.get(node_key).unwrap();
+            node.observer_count.set(node.observer_count.get() + 1);
+            node.last_update.get()
+        });
+        let shared = Rc::new(ObserverShared::new(node_key, value, last_update));
+        self.observers
+            .push(Rc::downgrade(&shared) as Weak<dyn ObserverTarget>);
+        Ok(Observer::new(
+            anchor.clone(),
+            shared,
+            Rc::clone(&self.graph.still_alive),
+        ))
     }
 
     /// Marks an Anchor as unobserved. If the `anchor` has parents that are necessary
@@ -91,13 +349,66 @@ impl Engine {
     where
         O: 'static,
     {
+        self.try_mark_unobserved(anchor)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Marks an Anchor as unobserved like `Engine::mark_unobserved`, but reports misuse as an
+    /// error instead of panicking.
+    pub fn try_mark_unobserved<O>(&mut self, anchor: &Anchor<O>) -> Result<(), Error>
+    where
+        O: 'static,
+    {
+        self.check_poisoned()?;
         self.with(|graph| {
-            let node = graph.get(anchor.key().node_key).unwrap();
+            let node = graph
+                .get(anchor.key().node_key)
+                .ok_or(Error::ForeignAnchor { requested_by: None })?;
             node.observed.set(false);
             Self::update_necessary_children(node);
+            Ok(())
+        })?;
+        #[cfg(feature = "serde")]
+        if let Some(recorder) = &self.recorder {
+            recorder.record_observed(anchor.key(), false);
+        }
+        Ok(())
+    }
+
+    /// Returns the generation of the latest stabilization.
+    pub fn current_generation(&self) -> Generation {
+        self.generation
+    }
+
+    /// Returns the generation of the latest stabilization that changed `anchor`'s value, unless
+    /// it was never calculated.
+    ///
+    /// This doesn't bring `anchor` up-to-date.
+    pub fn last_changed<O>(&self, anchor: &Anchor<O>) -> Option<Generation> {
+        self.try_last_changed(anchor)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Returns when `anchor` last changed like `Engine::last_changed`, but reports misuse as an
+    /// error instead of panicking.
+    pub fn try_last_changed<O>(&self, anchor: &Anchor<O>) -> Result<Option<Generation>, Error> {
+        self.with(|graph| {
+            let node = graph
+                .get(anchor.key().node_key)
+                .ok_or(Error::ForeignAnchor { requested_by: None })?;
+            Ok(node.last_update.get())
         })
     }
 
+    /// Returns whether a stabilization after `generation` changed `anchor`'s value.
+    ///
+    /// This doesn't bring `anchor` up-to-date. Use `Engine::try_last_changed` to report misuse
+    /// as an error instead of panicking.
+    pub fn changed_since<O>(&self, anchor: &Anchor<O>, generation: Generation) -> bool {
+        self.last_changed(anchor)
+            .map_or(false, |last_changed| last_changed > generation)
+    }
+
     pub(super) fn update_necessary_children(node: NodeGuard<'_>) {
         if Self::check_observed_raw(node) != ObservedState::Unnecessary {
             // we have another parent still observed, so skip this
@@ -111,13 +422,77 @@ impl Engine {
 
     /// Retrieves the value of an Anchor, recalculating dependencies as necessary to get the
     /// latest value.
+    ///
+    /// If some dependency is waiting for a future, this blocks the current thread until all of
+    /// them resolved.
     pub fn get<O>(&mut self, anchor: &Anchor<O>) -> O
     where
         O: 'static + Clone,
     {
+        self.try_get(anchor).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Retrieves the value of an Anchor like `Engine::get`, but reports misuse of the engine,
+    /// such as cyclic anchors, as an error instead of panicking.
+    pub fn try_get<O>(&mut self, anchor: &Anchor<O>) -> Result<O, Error>
+    where
+        O: 'static + Clone,
+    {
+        loop {
+            if let Some(output) = self.get_ready(anchor)? {
+                return Ok(output);
+            }
+            // futures wake their anchors through the remote inbox
+            self.graph.remote.inbox().wait_forever();
+        }
+    }
+
+    /// Retrieves the value of an Anchor like `Engine::get`, but yields to the executor
+    /// instead of blocking while some dependency is waiting for a future.
+    pub async fn get_async<O>(&mut self, anchor: &Anchor<O>) -> O
+    where
+        O: 'static + Clone,
+    {
+        self.try_get_async(anchor)
+            .await
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Retrieves the value of an Anchor like `Engine::get_async`, but reports misuse of the
+    /// engine as an error instead of panicking.
+    pub async fn try_get_async<O>(&mut self, anchor: &Anchor<O>) -> Result<O, Error>
+    where
+        O: 'static + Clone,
+    {
+        loop {
+            if let Some(output) = self.get_ready(anchor)? {
+                return Ok(output);
+            }
+            self.graph.remote.inbox().written().await;
+        }
+    }
+
+    /// Brings `anchor` up-to-date and returns its value, unless some dependency is still
+    /// waiting for a future.
+    fn get_ready<O>(&mut self, anchor: &Anchor<O>) -> Result<Option<O>, Error>
+    where
+        O: 'static + Clone,
+    {
+        if !self.accepts_key(anchor.key().node_key) {
+            return Err(Error::ForeignAnchor { requested_by: None });
+        }
+        self.with(|graph| {
+            let node = graph.get(anchor.key().node_key).unwrap();
+            if node.invalidated.get() {
+                return Err(Error::Invalidated {
+                    anchor: node.debug_info.get().to_string(),
+                });
+            }
+            Ok(())
+        })?;
         // stabilize once before, since the stabilization process may mark our requested node
         // as dirty
-        self.stabilize();
+        self.try_stabilize()?;
         self.with(|graph| {
             let anchor_node = graph.get(anchor.key().node_key).unwrap();
             if super::graph::recalc_state(anchor_node) != RecalcState::Ready {
@@ -125,20 +500,30 @@ impl Engine {
                 // stabilize again, to make sure our target node that is now in the queue is up-to-date
                 // use stabilize0 because no dirty marks have occurred since last stabilization, and we want
                 // to make sure we don't unnecessarily increment generation number
-                self.stabilize0();
+                self.stabilize0()?;
             }
-            let target_anchor = &graph.get(anchor.key().node_key).unwrap().anchor;
-            let borrow = target_anchor.borrow();
-            borrow
-                .as_ref()
-                .unwrap()
-                .output(&mut EngineContext::new(self))
-                .downcast_ref::<O>()
-                .unwrap()
-                .clone()
+            let anchor_node = graph.get(anchor.key().node_key).unwrap();
+            if super::graph::recalc_state(anchor_node) != RecalcState::Ready {
+                return Ok(None);
+            }
+            Ok(Some(self.cloned_output(anchor_node)))
         })
     }
 
+    pub(super) fn cloned_output<O>(&self, node: NodeGuard<'_>) -> O
+    where
+        O: 'static + Clone,
+    {
+        let borrow = node.anchor.borrow();
+        borrow
+            .as_ref()
+            .unwrap()
+            .output(&mut EngineContext::new(self))
+            .downcast_ref::<O>()
+            .unwrap()
+            .clone()
+    }
+
     pub(super) fn accepts_key(&self, key: NodeKey) -> bool {
         self.graph.accepts_key(key)
     }
@@ -152,7 +537,15 @@ impl Engine {
         }
     }
 
+    pub(super) fn remote_waker(&self, dirty_handle: &Rc<DirtyHandle>) -> Waker {
+        self.graph
+            .remote
+            .waker(Box::new(Rc::downgrade(dirty_handle)))
+    }
+
     pub(crate) fn update_dirty_marks(&mut self) {
+        // writes from remote setters are turned into dirty marks, too
+        self.graph.remote.apply_writes();
         self.with(|graph| {
             let dirty_marks = std::mem::take(&mut *self.dirty_marks.borrow_mut());
             for dirty in dirty_marks {
@@ -162,22 +555,114 @@ impl Engine {
         })
     }
 
+    /// Blocks the current thread until a `VariableSetter` has set a variable of this engine, or
+    /// a future has woken one of its anchors, since the last stabilization, or until `timeout`
+    /// has passed.
+    ///
+    /// Returns whether there are changes waiting to be picked up by the next stabilization.
+    pub fn wait_for_changes(&self, timeout: Duration) -> bool {
+        self.graph.remote.inbox().wait(timeout)
+    }
+
+    /// Releases memory the engine held on to after recalculating unusually high anchors.
+    ///
+    /// It's allocated again on demand, should they need recalculating again.
+    pub fn shrink_to_fit(&mut self) {
+        self.graph.shrink_recalc_queues();
+    }
+
     /// Ensure any Observed nodes are up-to-date, recalculating dependencies as necessary. You
     /// should rarely need to call this yourself; `Engine::get` calls it automatically.
     pub fn stabilize(&mut self) {
+        self.try_stabilize().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Ensure any Observed nodes are up-to-date like `Engine::stabilize`, but reports misuse
+    /// of the engine, such as cyclic anchors, as an error instead of panicking.
+    ///
+    /// Stabilization stops at the first error, leaving the remaining nodes queued for the
+    /// next one.
+    pub fn try_stabilize(&mut self) -> Result<(), Error> {
+        self.check_poisoned()?;
         self.update_dirty_marks();
         self.generation.increment();
-        self.stabilize0();
+        self.stabilize0()?;
+        self.notify_observers();
+        #[cfg(feature = "serde")]
+        if let Some(recorder) = &self.recorder {
+            recorder.record_stabilization(self);
+        }
+        Ok(())
+    }
+
+    /// Logs how this engine is driven into `recorder` from now on, replacing any recorder
+    /// that was attached before.
+    #[cfg(feature = "serde")]
+    pub fn record(&mut self, recorder: &Recorder) {
+        self.stop_recording();
+        recorder.start();
+        self.recorder = Some(recorder.clone());
+    }
+
+    /// Detaches the recorder attached by `Engine::record`, if any.
+    #[cfg(feature = "serde")]
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            recorder.stop();
+        }
+    }
+
+    /// Returns the anchor computing query `Q` for `key`, creating it the first time it's asked
+    /// for.
+    ///
+    /// Later calls with an equal key return the same anchor, until it's evicted by
+    /// `Engine::evict_unused_queries`.
+    #[track_caller]
+    pub fn query<Q: Query>(&self, key: Q::Key) -> Anchor<Q::Value> {
+        // closures don't inherit `#[track_caller]`
+        let location = Location::caller();
+        self.enter(|| Queries::anchor::<Q>(&self.queries, &key, location))
+    }
+
+    /// Forgets the anchors of all queries that are neither observed nor necessary, returning
+    /// how many were evicted.
+    ///
+    /// Anchors that are still used elsewhere keep working, but asking for the same query again
+    /// creates a new anchor, which is executed from scratch.
+    pub fn evict_unused_queries(&mut self) -> usize {
+        let evicted = self.queries.borrow_mut().evict_unused(self);
+        evicted.len()
+    }
+
+    fn notify_observers(&mut self) {
+        self.observers
+            .retain(|observer| observer.strong_count() > 0);
+        let observers: Vec<_> = self.observers.iter().filter_map(Weak::upgrade).collect();
+        for observer in observers {
+            observer.notify(self);
+        }
+    }
+
+    fn check_poisoned(&self) -> Result<(), Error> {
+        if self.stabilizing.get() {
+            // an earlier stabilization never finished, so it must have panicked
+            Err(Error::Poisoned)
+        } else {
+            Ok(())
+        }
     }
 
     /// internal function for stabilization. does not update dirty marks or increment the stabilization number
-    fn stabilize0(&self) {
-        self.with(|graph| {
+    fn stabilize0(&self) -> Result<(), Error> {
+        // anchors created by `then` closures belong to this engine, whichever one is current
+        let _mounter = self.enter_guard();
+        self.stabilizing.set(true);
+        let res = self.with(|graph| {
             while let Some((height, node)) = graph.recalc_pop_next() {
                 let calculation_complete = if super::graph::height(node) == height {
                     // TODO with new graph we can automatically relocate nodes if their height changes
                     // this nodes height is current, so we can recalculate
-                    self.recalculate(graph, node)
+                    self.recalculate(graph, node)?
                 } else {
                     // skip calculation, redo at correct height
                     false
@@ -187,11 +672,19 @@ impl Engine {
                     graph.queue_recalc(node);
                 }
             }
-        })
+            Ok(())
+        });
+        self.stabilizing.set(false);
+        res
     }
 
     /// returns false if calculation is still pending
-    fn recalculate<'a>(&self, graph: GraphGuard<'a>, node: NodeGuard<'a>) -> bool {
+    fn recalculate<'a>(&self, graph: GraphGuard<'a>, node: NodeGuard<'a>) -> Result<bool, Error> {
+        if node.invalidated.get() {
+            return Err(Error::Invalidated {
+                anchor: node.debug_info.get().to_string(),
+            });
+        }
         let this_anchor = &node.anchor;
         let mut ecx = EngineContextMut::new(self, graph, node);
         let poll_result = this_anchor
@@ -199,7 +692,12 @@ impl Engine {
             .as_mut()
             .unwrap()
             .poll_updated(&mut ecx);
-        match poll_result {
+        if let Some(err) = ecx.take_error() {
+            // the node bailed, so it needs to be polled again once the problem is fixed
+            super::graph::needs_recalc(node);
+            return Err(err);
+        }
+        let calculation_complete = match poll_result {
             Poll::Pending => {
                 if ecx.pending_on_anchor_get() {
                     // looks like we requested an anchor that isn't yet calculated, so we
@@ -207,9 +705,10 @@ impl Engine {
                     // requested anchor's already, or it was updated so it's higher now.
                     false
                 } else {
-                    // in the future, this means we polled on some non-anchors future. since
-                    // that isn't supported for now, this just means something went wrong
-                    panic!("poll_updated return pending without requesting another anchor");
+                    // we're waiting for some non-anchors future, or for an anchor that is
+                    // waiting itself. polling again is pointless until we're woken up.
+                    super::graph::wait_for_wake(node);
+                    true
                 }
             }
             Poll::Updated => {
@@ -223,51 +722,89 @@ impl Engine {
                 node.last_ready.set(Some(self.generation));
                 true
             }
-        }
+        };
+        Ok(calculation_complete)
     }
 
-    /// Returns a debug string containing the current state of the recomputation graph.
+    /// Returns a table of every live node in the graph, one per line.
     pub fn debug_state(&self) -> String {
-        let debug = "".to_string();
-        // for (node_id, _) in nodes.iter() {
-        //     let node = self.graph.get(node_id).unwrap();
-        //     let necessary = if self.graph.is_necessary(node_id) {
-        //         "necessary"
-        //     } else {
-        //         "   --    "
-        //     };
-        //     let observed = if Self::check_observed_raw(node) == ObservedState::Observed {
-        //         "observed"
-        //     } else {
-        //         "   --   "
-        //     };
-        //     let state = match self.to_recalculate.borrow_mut().state(node_id) {
-        //         RecalcState::NeedsRecalc => "NeedsRecalc  ",
-        //         RecalcState::PendingRecalc => "PendingRecalc",
-        //         RecalcState::Ready => "Ready        ",
-        //     };
-        //     debug += &format!(
-        //         "{:>80}  {}  {}  {}\n",
-        //         node.debug_info.get().to_string(),
-        //         necessary,
-        //         observed,
-        //         state
-        //     );
-        // }
-        #[allow(clippy::let_and_return)]
+        let mut debug = format!("{}\n", NodeDebugRow::HEADER);
+        for row in self.debug_rows() {
+            debug += &format!("{}\n", row);
+        }
         debug
     }
 
+    /// Returns the state of every live node in the graph, in the order they were first
+    /// allocated.
+    pub fn debug_rows(&self) -> Vec<NodeDebugRow> {
+        self.with(|graph| {
+            graph
+                .live_nodes()
+                .into_iter()
+                .map(|node| NodeDebugRow {
+                    debug_info: node.debug_info.get(),
+                    height: super::graph::height(node),
+                    recalc_state: super::graph::recalc_state(node),
+                    observed_state: Self::check_observed_raw(node),
+                    necessary_count: node.necessary_count.get(),
+                    handle_count: node.ptrs.handle_count.get(),
+                    last_ready: node.last_ready.get().map(Generation::get),
+                    last_update: node.last_update.get().map(Generation::get),
+                })
+                .collect()
+        })
+    }
+
+    /// Renders every live node of the graph in Graphviz's DOT language.
+    ///
+    /// Nodes are labeled with their debug location and height, filled according to their
+    /// `ObservedState` (gold if observed, light blue if necessary, white otherwise) and outlined
+    /// according to their `RecalcState` (black if ready, red if needed, orange if pending and
+    /// purple if waiting). Edges point from inputs to the anchors using them: solid ones for
+    /// clean parents, dashed ones for necessary children.
+    pub fn to_dot(&self) -> String {
+        self.with(|graph| super::dot::to_dot(graph, None))
+    }
+
+    /// Renders `anchor` and every node it depended on during its last recalculation like
+    /// `Engine::to_dot`.
+    pub fn to_dot_for<O>(&self, anchor: &Anchor<O>) -> String {
+        self.try_to_dot_for(anchor)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Renders `anchor` and its dependencies like `Engine::to_dot_for`, but reports misuse as
+    /// an error instead of panicking.
+    pub fn try_to_dot_for<O>(&self, anchor: &Anchor<O>) -> Result<String, Error> {
+        self.with(|graph| {
+            let node = graph
+                .get(anchor.key().node_key)
+                .ok_or(Error::ForeignAnchor { requested_by: None })?;
+            Ok(super::dot::to_dot(graph, Some(node)))
+        })
+    }
+
+    /// Returns whether an Anchor is Observed, Necessary, or Unnecessary.
     pub fn check_observed<T>(&self, anchor: &Anchor<T>) -> ObservedState {
+        self.try_check_observed(anchor)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Returns whether an Anchor is observed like `Engine::check_observed`, but reports misuse
+    /// as an error instead of panicking.
+    pub fn try_check_observed<T>(&self, anchor: &Anchor<T>) -> Result<ObservedState, Error> {
         self.with(|graph| {
-            let node = graph.get(anchor.key().node_key).unwrap();
-            Self::check_observed_raw(node)
+            let node = graph
+                .get(anchor.key().node_key)
+                .ok_or(Error::ForeignAnchor { requested_by: None })?;
+            Ok(Self::check_observed_raw(node))
         })
     }
 
     /// Returns whether an Anchor is Observed, Necessary, or Unnecessary.
     pub(super) fn check_observed_raw(node: NodeGuard<'_>) -> ObservedState {
-        if node.observed.get() {
+        if node.observed.get() || node.observer_count.get() > 0 {
             return ObservedState::Observed;
         }
 
@@ -278,3 +815,59 @@ impl Engine {
         }
     }
 }
+
+#[cfg(feature = "serde")]
+impl Engine {
+    /// Registers `variable` under `key`, so its value is included in this engine's snapshots.
+    ///
+    /// Panics if another variable is already registered under `key`.
+    pub fn register_variable<T>(&mut self, key: impl Into<String>, variable: &super::Variable<T>)
+    where
+        T: 'static + serde::Serialize + serde::de::DeserializeOwned,
+    {
+        self.snapshots
+            .register(key.into(), Box::new(variable.clone()));
+    }
+
+    /// Removes the variable registered under `key` from this engine's snapshots.
+    ///
+    /// Returns whether a variable was registered under it.
+    pub fn unregister_variable(&mut self, key: &str) -> bool {
+        self.snapshots.unregister(key)
+    }
+
+    /// Captures the current values of all registered variables.
+    pub fn snapshot(&self) -> Result<crate::core::Snapshot, crate::core::SnapshotError> {
+        self.snapshots.snapshot()
+    }
+
+    /// Sets all variables in `snapshot` to their captured values at once, like a
+    /// `Transaction` would.
+    ///
+    /// Variables whose values are unchanged aren't set at all, so only anchors depending on
+    /// changed values are recalculated. If any value can't be restored, no variable is set.
+    /// While history is enabled, the restored values are recorded as one batch.
+    pub fn restore(
+        &mut self,
+        snapshot: &crate::core::Snapshot,
+    ) -> Result<(), crate::core::SnapshotError> {
+        let transaction = self.snapshots.stage_restore(snapshot)?;
+        self.commit(transaction);
+        Ok(())
+    }
+}
+
+/// Mounts new anchors onto an engine, until dropped.
+///
+/// Returned by `Engine::enter_guard`. Dropping it makes the previously current engine current
+/// again.
+pub struct EnterGuard {
+    previous: Option<Rc<Graph>>,
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        DEFAULT_MOUNTER.with(|v| *v.borrow_mut() = previous);
+    }
+}
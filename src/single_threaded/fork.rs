@@ -0,0 +1,122 @@
+use std::{any::Any, collections::HashMap};
+
+use crate::Error;
+
+use super::{Anchor, AnchorKey, Engine, Variable};
+
+/// A speculative copy of part of an engine's graph, for asking "what if" questions without
+/// disturbing the engine.
+///
+/// Created by `Engine::fork`. The fork has its own graph, which copies of the engine's
+/// variables feed into. Overriding them with `Fork::set` only recalculates the fork's anchors,
+/// so the engine's cached values and generations stay untouched, and dropping the fork
+/// discards everything it calculated.
+///
+/// Anchors can't be copied, so `build` mounts the part of the graph the question is about
+/// again. The fork calculates it once, and afterwards only what overrides affect.
+///
+/// ```
+/// use anchors::{MultiAnchor, single_threaded::*};
+///
+/// let mut engine = Engine::new();
+/// let price = Variable::new(10);
+/// let quantity = Variable::new(3);
+/// let total = (&price.watch(), &quantity.watch()).map(|price, quantity| price * quantity);
+/// assert_eq!(engine.get(&total), 30);
+///
+/// let mut fork = engine.fork(|fork| {
+///     let (price, quantity) = (fork.variable(&price), fork.variable(&quantity));
+///     (&price.watch(), &quantity.watch()).map(|price, quantity| price * quantity)
+/// });
+/// let fork_total = fork.outputs().clone();
+/// fork.set(&price, 12);
+/// assert_eq!(fork.get(&fork_total), 36);
+///
+/// // the engine's variables keep their values
+/// assert_eq!(*price.get(), 10);
+/// assert_eq!(engine.get(&total), 30);
+/// ```
+pub struct Fork<G> {
+    outputs: G,
+    // the fork's copies, by the key of the engine's variable they copy
+    variables: HashMap<AnchorKey, Box<dyn Any>>,
+    // dropped last, after all anchors mounted onto it
+    engine: Engine,
+}
+
+/// Copies an engine's variables into a `Fork` while it's built.
+pub struct ForkBuilder {
+    variables: HashMap<AnchorKey, Box<dyn Any>>,
+}
+
+impl ForkBuilder {
+    /// Returns the fork's copy of `variable`, which starts out with `variable`'s current value.
+    ///
+    /// Copying the same variable again returns the same copy.
+    #[track_caller]
+    pub fn variable<T: 'static>(&mut self, variable: &Variable<T>) -> Variable<T> {
+        self.variables
+            .entry(variable.watch().key())
+            .or_insert_with(|| Box::new(Variable::from_arc(variable.get())))
+            .downcast_ref::<Variable<T>>()
+            .unwrap()
+            .clone()
+    }
+}
+
+impl<G> Fork<G> {
+    pub(super) fn new(fork_engine: Engine, build: impl FnOnce(&mut ForkBuilder) -> G) -> Self {
+        let mut builder = ForkBuilder {
+            variables: HashMap::new(),
+        };
+        let outputs = fork_engine.enter(|| build(&mut builder));
+        Self {
+            outputs,
+            variables: builder.variables,
+            engine: fork_engine,
+        }
+    }
+
+    /// Returns what `build` returned.
+    pub fn outputs(&self) -> &G {
+        &self.outputs
+    }
+
+    /// Overrides the fork's copy of `variable` with `value`.
+    ///
+    /// Panics if `build` didn't copy `variable`.
+    pub fn set<T: 'static>(&self, variable: &Variable<T>, value: T) {
+        self.copy_of(variable).set(value);
+    }
+
+    /// Sets the fork's copy of `variable` back to `variable`'s current value.
+    ///
+    /// Panics if `build` didn't copy `variable`.
+    pub fn reset<T: 'static>(&self, variable: &Variable<T>) {
+        self.copy_of(variable).replace(variable.get());
+    }
+
+    /// Returns the value of `anchor`, which has to be mounted onto the fork, recalculating it
+    /// if necessary.
+    pub fn get<O>(&mut self, anchor: &Anchor<O>) -> O
+    where
+        O: 'static + Clone,
+    {
+        self.engine.get(anchor)
+    }
+
+    /// Like `Fork::get`, but reports errors instead of panicking.
+    pub fn try_get<O>(&mut self, anchor: &Anchor<O>) -> Result<O, Error>
+    where
+        O: 'static + Clone,
+    {
+        self.engine.try_get(anchor)
+    }
+
+    fn copy_of<T: 'static>(&self, variable: &Variable<T>) -> &Variable<T> {
+        self.variables
+            .get(&variable.watch().key())
+            .and_then(|copy| copy.downcast_ref())
+            .expect("attempted to override a variable the fork didn't copy")
+    }
+}
@@ -3,19 +3,25 @@ use std::{
     rc::Rc,
 };
 
-use crate::arena;
+use crate::{arena, core::AnchorDebugInfo, Error};
 
 use super::{
-    node::Node, AnchorDebugInfo, AnchorHandle, GenericAnchor, GraphGuard, NodeGuard, NodeKey,
-    NodePtr, NodePtrs,
+    node::Node, AnchorHandle, Engine, GenericAnchor, GraphGuard, NodeGuard, NodeKey, NodePtr,
+    NodePtrs, Remote,
 };
 
+/// Whether a node is up-to-date, as reported by `Engine::debug_rows`.
 #[derive(Copy, Clone, Default, Eq, PartialEq, Hash, Debug)]
-pub(super) enum RecalcState {
+pub enum RecalcState {
+    /// The node needs to be recalculated before its output can be used.
     #[default]
     Needed,
+    /// The node is queued for recalculation.
     Pending,
+    /// The node is up-to-date.
     Ready,
+    /// The node was polled, but is waiting for some future to wake it up.
+    Waiting,
 }
 
 thread_local! {
@@ -28,17 +34,29 @@ pub(super) struct Graph {
 
     pub(super) still_alive: Rc<Cell<bool>>,
 
-    /// height -> first node in that height's queue
+    /// collects writes from other threads, and wakeups of futures
+    pub(super) remote: Remote,
+
+    /// `None` if nodes may grow arbitrarily high
+    pub(super) max_height: Option<usize>,
+
+    /// height -> first node in that height's queue; grows on demand
     pub(super) recalc_queues: RefCell<Vec<Option<NodePtr>>>,
     pub(super) recalc_min_height: Cell<usize>,
     pub(super) recalc_max_height: Cell<usize>,
 
+    /// every node ever allocated, whether it's live or free
+    pub(super) all_nodes: RefCell<Vec<NodePtr>>,
+
     /// pointer to head of linked list of free nodes
     pub(super) free_head: Box<Cell<Option<NodePtr>>>,
+
+    /// handles of the anchors mounted during each scope that's currently open, innermost last
+    pub(super) open_scopes: RefCell<Vec<Vec<AnchorHandle>>>,
 }
 
 impl Graph {
-    pub fn new(max_height: usize) -> Self {
+    pub fn new(max_height: Option<usize>) -> Self {
         Self {
             nodes: arena::Graph::new(),
             token: NEXT_TOKEN.with(|token| {
@@ -46,12 +64,25 @@ impl Graph {
                 token.set(n + 1);
                 n
             }),
-            recalc_queues: RefCell::new(vec![None; max_height]),
-            recalc_min_height: Cell::new(max_height),
+            max_height,
+            recalc_queues: RefCell::new(vec![]),
+            recalc_min_height: Cell::new(0),
             recalc_max_height: Cell::new(0),
             still_alive: Rc::new(Cell::new(true)),
+            remote: Remote::new(),
+            all_nodes: RefCell::new(vec![]),
             free_head: Box::new(Cell::new(None)),
+            open_scopes: RefCell::new(vec![]),
+        }
+    }
+
+    /// Releases the memory held by trailing empty recalculation queues.
+    pub(super) fn shrink_recalc_queues(&self) {
+        let mut recalc_queues = self.recalc_queues.borrow_mut();
+        while let Some(None) = recalc_queues.last() {
+            recalc_queues.pop();
         }
+        recalc_queues.shrink_to_fit();
     }
 
     pub(super) fn accepts_key(&self, node_key: NodeKey) -> bool {
@@ -68,9 +99,11 @@ impl Graph {
     pub(super) fn insert_testing(&self) -> AnchorHandle {
         use std::panic::Location;
 
-        use crate::single_threaded::ConstAnchor;
+        use std::sync::Arc;
 
-        let anchor = ConstAnchor::new(Rc::new(123), Location::caller());
+        use crate::core::ConstAnchor;
+
+        let anchor = ConstAnchor::new(Arc::new(123), Location::caller());
 
         self.insert(
             Box::new(anchor),
@@ -95,6 +128,7 @@ impl Graph {
                     next_node.ptrs.prev.set(None);
                 }
                 node.observed.set(false);
+                node.observer_count.set(0);
                 node.visited.set(false);
                 node.necessary_count.set(0);
                 node.ptrs.clean_parent0.set(None);
@@ -108,11 +142,14 @@ impl Graph {
                 node.debug_info.set(debug_info);
                 node.last_ready.set(None);
                 node.last_update.set(None);
+                node.waker.replace(None);
+                node.invalidated.set(false);
                 node.anchor.replace(Some(anchor));
                 node
             } else {
                 let node = Node {
                     observed: Cell::new(false),
+                    observer_count: Cell::new(0),
                     visited: Cell::new(false),
                     necessary_count: Cell::new(0),
                     token: self.token,
@@ -125,17 +162,27 @@ impl Graph {
                         recalc_state: Cell::new(RecalcState::Needed),
                         necessary_children: RefCell::new(vec![]),
                         height: Cell::new(0),
+                        queued_height: Cell::new(0),
                         handle_count: Cell::new(1),
                     },
                     debug_info: Cell::new(debug_info),
                     last_ready: Cell::new(None),
                     last_update: Cell::new(None),
+                    waker: RefCell::new(None),
+                    scoped: RefCell::new(vec![]),
+                    invalidated: Cell::new(false),
                     anchor: RefCell::new(Some(anchor)),
                 };
-                nodes.insert(node)
+                let node = nodes.insert(node);
+                self.all_nodes.borrow_mut().push(unsafe { node.make_ptr() });
+                node
             };
             let num = NodeKey::new(unsafe { ptr.make_ptr() }, self.token);
-            AnchorHandle::new(num, Rc::clone(&self.still_alive))
+            let handle = AnchorHandle::new(num, Rc::clone(&self.still_alive));
+            if let Some(scope) = self.open_scopes.borrow_mut().last_mut() {
+                scope.push(handle.clone());
+            }
+            handle
         })
     }
 }
@@ -146,11 +193,12 @@ impl Drop for Graph {
     }
 }
 
-#[allow(clippy::result_unit_err)] // FIXME
+/// Returns `Ok(true)` if `child` was already lower than `parent`, and `Ok(false)` if `parent`'s
+/// height had to be increased.
 pub(super) fn ensure_height_increases<'a>(
     child: NodeGuard<'a>,
     parent: NodeGuard<'a>,
-) -> Result<bool, ()> {
+) -> Result<bool, Error> {
     if height(child) < height(parent) {
         return Ok(true);
     }
@@ -160,30 +208,38 @@ pub(super) fn ensure_height_increases<'a>(
     res.map(|()| false)
 }
 
-#[allow(clippy::result_unit_err)] // FIXME
-pub(super) fn set_min_height(node: NodeGuard<'_>, min_height: usize) -> Result<(), ()> {
+pub(super) fn set_min_height(node: NodeGuard<'_>, min_height: usize) -> Result<(), Error> {
     if node.visited.get() {
-        return Err(());
+        return Err(Error::Cycle {
+            path: vec![node.debug_info.get().to_string()],
+        });
+    }
+
+    if let Some(max_height) = unsafe { &*node.ptrs.graph }.max_height {
+        if min_height >= max_height {
+            return Err(Error::HeightExceeded {
+                anchor: node.debug_info.get().to_string(),
+                max_height,
+            });
+        }
     }
 
     node.visited.set(true);
 
+    let mut res = Ok(());
     if height(node) < min_height {
         node.ptrs.height.set(min_height);
-        let mut did_err = false;
         for parent in node.clean_parents() {
-            if let Err(_loop_ids) = set_min_height(parent, min_height + 1) {
-                did_err = true;
+            if let Err(err) = set_min_height(parent, min_height + 1) {
+                // report the first error, but keep raising the remaining parents
+                res = res.and(Err(err.via(|| node.debug_info.get().to_string())));
             }
         }
-        if did_err {
-            return Err(());
-        }
     }
 
     node.visited.set(false);
 
-    Ok(())
+    res
 }
 
 pub(super) unsafe fn free(ptr: NodePtr) {
@@ -204,8 +260,39 @@ pub(super) unsafe fn free(ptr: NodePtr) {
     guard.ptrs.next.set(old_free);
     free_head.set(Some(ptr));
 
+    // wakers of freed nodes must not mark whichever node reuses this slot as dirty
+    *guard.waker.borrow_mut() = None;
+
     // "SAFETY": this may cause other nodes to be dropped, so do with care
     *guard.anchor.borrow_mut() = None;
+    let scoped = guard.scoped.take();
+    drop(scoped);
+}
+
+/// Invalidates `node` and everything mounted in its latest scope, dropping their cores so they
+/// release their inputs.
+pub(super) fn invalidate(node: NodeGuard<'_>) {
+    if node.invalidated.replace(true) {
+        return;
+    }
+    let graph = unsafe { &*node.ptrs.graph };
+    dequeue_calc(graph, node);
+    node.ptrs.recalc_state.set(RecalcState::Needed);
+    for child in node.drain_necessary_children() {
+        Engine::update_necessary_children(child);
+    }
+    *node.waker.borrow_mut() = None;
+
+    let scoped = node.scoped.take();
+    for handle in &scoped {
+        if let Some(scoped) = handle.node() {
+            invalidate(scoped);
+        }
+    }
+    // "SAFETY": this may cause other nodes to be dropped, so do with care
+    let anchor = node.anchor.borrow_mut().take();
+    drop(anchor);
+    drop(scoped);
 }
 
 fn dequeue_calc(graph: &Graph, node: NodeGuard<'_>) {
@@ -220,20 +307,13 @@ fn dequeue_calc(graph: &Graph, node: NodeGuard<'_>) {
             .set(node.ptrs.next.get());
     } else {
         // node was first in queue, need to set queue head to next
-        let mut recalc_queues = graph.recalc_queues.borrow_mut();
-        let height = node.ptrs.height.get();
-        let next = node.ptrs.next.get();
-        assert_eq!(
-            recalc_queues[height].map(|ptr| unsafe { ptr.lookup_unchecked() }),
-            Some(node.0)
-        );
-        recalc_queues[height] = next;
+        graph.recalc_queues.borrow_mut()[node.ptrs.queued_height.get()] = node.ptrs.next.get();
     }
 
     if let Some(next) = node.ptrs.next.get() {
         unsafe { next.lookup_unchecked() }
             .ptrs
-            .next
+            .prev
             .set(node.ptrs.prev.get());
     }
 
@@ -246,7 +326,10 @@ pub(super) fn height(node: NodeGuard<'_>) -> usize {
 }
 
 pub(super) fn needs_recalc(node: NodeGuard<'_>) {
-    if node.ptrs.recalc_state.get() != RecalcState::Ready {
+    if !matches!(
+        node.ptrs.recalc_state.get(),
+        RecalcState::Ready | RecalcState::Waiting
+    ) {
         // already in recalc queue, or already pending recalc
         return;
     }
@@ -254,6 +337,10 @@ pub(super) fn needs_recalc(node: NodeGuard<'_>) {
     node.ptrs.recalc_state.set(RecalcState::Needed);
 }
 
+pub(super) fn wait_for_wake(node: NodeGuard<'_>) {
+    node.ptrs.recalc_state.set(RecalcState::Waiting);
+}
+
 pub(super) fn recalc_state(node: NodeGuard<'_>) -> RecalcState {
     node.ptrs.recalc_state.get()
 }
@@ -6,7 +6,7 @@ fn to_vec<I: std::iter::Iterator>(iter: I) -> Vec<I::Item> {
 
 #[test]
 fn set_edge_updates_correctly() {
-    let graph = Graph::new(256);
+    let graph = Graph::new(Some(256));
 
     graph.with(|guard| {
         let a = guard.insert_testing_guard();
@@ -64,7 +64,7 @@ fn set_edge_updates_correctly() {
 
 #[test]
 fn height_calculated_correctly() {
-    let graph = Graph::new(256);
+    let graph = Graph::new(Some(256));
 
     graph.with(|guard| {
         let a = guard.insert_testing_guard();
@@ -103,7 +103,7 @@ fn height_calculated_correctly() {
 
 #[test]
 fn cycles_cause_error() {
-    let graph = Graph::new(256);
+    let graph = Graph::new(Some(256));
 
     graph.with(|guard| {
         let b = guard.insert_testing_guard();
@@ -116,7 +116,7 @@ fn cycles_cause_error() {
 
 #[test]
 fn non_cycles_wont_cause_errors() {
-    let graph = Graph::new(256);
+    let graph = Graph::new(Some(256));
 
     graph.with(|guard| {
         let a = guard.insert_testing_guard();
@@ -140,7 +140,7 @@ fn non_cycles_wont_cause_errors() {
 
 #[test]
 fn test_insert_pop() {
-    let graph = Graph::new(10);
+    let graph = Graph::new(Some(10));
 
     graph.with(|guard| {
         let a = guard.insert_testing_guard();
@@ -185,7 +185,7 @@ fn test_insert_pop() {
 #[test]
 #[should_panic]
 fn test_insert_above_max_height() {
-    let graph = Graph::new(10);
+    let graph = Graph::new(Some(10));
 
     graph.with(|guard| {
         let a = guard.insert_testing_guard();
@@ -194,11 +194,35 @@ fn test_insert_above_max_height() {
     })
 }
 
+#[test]
+fn test_queues_grow_on_demand() {
+    let graph = Graph::new(None);
+
+    graph.with(|guard| {
+        let a = guard.insert_testing_guard();
+        let b = guard.insert_testing_guard();
+        set_min_height(a, 1000).unwrap();
+        guard.queue_recalc(a);
+        guard.queue_recalc(b);
+        assert_eq!(graph.recalc_queues.borrow().len(), 1001);
+
+        assert_eq!(guard.recalc_pop_next().map(|(height, _)| height), Some(0));
+        assert_eq!(
+            guard.recalc_pop_next().map(|(height, _)| height),
+            Some(1000)
+        );
+        assert!(guard.recalc_pop_next().is_none());
+    });
+
+    graph.shrink_recalc_queues();
+    assert!(graph.recalc_queues.borrow().is_empty());
+}
+
 #[test]
 fn test_free_list() {
     use crate::core::AnchorHandle;
 
-    let graph = Graph::new(10);
+    let graph = Graph::new(Some(10));
 
     let a = graph.insert_testing();
     let b = graph.insert_testing();
@@ -31,9 +31,22 @@ impl<'gg> GraphGuard<'gg> {
         guard
     }
 
+    /// Returns all nodes that weren't freed, in the order they were first allocated.
+    pub(super) fn live_nodes(&self) -> Vec<NodeGuard<'gg>> {
+        self.graph
+            .all_nodes
+            .borrow()
+            .iter()
+            .map(|ptr| NodeGuard(unsafe { self.nodes.lookup_ptr(*ptr) }))
+            .filter(|node| node.anchor.borrow().is_some())
+            .collect()
+    }
+
     pub(super) fn recalc_pop_next(&self) -> Option<(usize, NodeGuard<'gg>)> {
         let mut recalc_queues = self.graph.recalc_queues.borrow_mut();
-        while self.graph.recalc_min_height.get() <= self.graph.recalc_max_height.get() {
+        while self.graph.recalc_min_height.get() <= self.graph.recalc_max_height.get()
+            && self.graph.recalc_min_height.get() < recalc_queues.len()
+        {
             if let Some(ptr) = recalc_queues[self.graph.recalc_min_height.get()] {
                 let node = unsafe { self.nodes.lookup_ptr(ptr) };
                 recalc_queues[self.graph.recalc_min_height.get()] = node.ptrs.next.get();
@@ -64,9 +77,10 @@ impl<'gg> GraphGuard<'gg> {
         }
         node.ptrs.recalc_state.set(RecalcState::Pending);
         let node_height = super::height(node);
+        node.ptrs.queued_height.set(node_height);
         let mut recalc_queues = self.graph.recalc_queues.borrow_mut();
         if node_height >= recalc_queues.len() {
-            panic!("too large height error");
+            recalc_queues.resize(node_height + 1, None);
         }
         if let Some(old) = recalc_queues[node_height] {
             unsafe { self.nodes.lookup_ptr(old) }
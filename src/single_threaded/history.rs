@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+
+use crate::core::Change;
+
+/// The batches of variable writes committed by transactions, which can be undone and redone.
+pub(super) struct History {
+    undo: VecDeque<Vec<Box<dyn Change>>>,
+    redo: Vec<Vec<Box<dyn Change>>>,
+    depth: usize,
+}
+
+impl History {
+    pub(super) fn new(depth: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: vec![],
+            depth,
+        }
+    }
+
+    /// Records a committed batch, forgetting the oldest one once there are more than `depth`.
+    /// Anything undone before can't be redone anymore.
+    pub(super) fn record(&mut self, batch: Vec<Box<dyn Change>>) {
+        if batch.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push_back(batch);
+        self.truncate();
+    }
+
+    pub(super) fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        self.truncate();
+    }
+
+    pub(super) fn undo(&mut self) -> bool {
+        match self.undo.pop_back() {
+            Some(batch) => {
+                for change in batch.iter().rev() {
+                    change.undo();
+                }
+                self.redo.push(batch);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(super) fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(batch) => {
+                for change in &batch {
+                    change.redo();
+                }
+                self.undo.push_back(batch);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(super) fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub(super) fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    fn truncate(&mut self) {
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+}
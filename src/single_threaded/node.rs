@@ -1,12 +1,23 @@
-use std::cell::{Cell, RefCell};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    task::Waker,
+};
 
 use crate::arena;
 
-use super::{generation::Generation, node_ptrs::NodePtrs, AnchorDebugInfo, GenericAnchor};
+use crate::core::AnchorDebugInfo;
+
+use crate::core::Generation;
+
+use super::{node_ptrs::NodePtrs, AnchorHandle, DirtyHandle, GenericAnchor};
 
 pub(super) struct Node {
     pub observed: Cell<bool>,
 
+    /// Number of live `Observer`s of this node.
+    pub observer_count: Cell<usize>,
+
     /// Bool used during height incrementing to check for loops
     pub visited: Cell<bool>,
 
@@ -22,6 +33,18 @@ pub(super) struct Node {
     /// Tracks when this `Node` was` last polled as `Updated`.
     pub(super) last_update: Cell<Option<Generation>>,
 
+    /// The waker handed out while polling this node, along with the dirty handle it marks.
+    ///
+    /// Dropping the dirty handle disarms the waker.
+    pub(super) waker: RefCell<Option<(Rc<DirtyHandle>, Waker)>>,
+
+    /// Handles of the anchors mounted during this node's latest scope, see
+    /// `UpdateContext::begin_scope`.
+    pub(super) scoped: RefCell<Vec<AnchorHandle>>,
+
+    /// Set once the scope this node was mounted in was replaced; it's never recalculated again.
+    pub(super) invalidated: Cell<bool>,
+
     /// `Some(_)`` if this node is still active, `None`` otherwise
     pub(super) anchor: RefCell<Option<Box<dyn GenericAnchor>>>,
 
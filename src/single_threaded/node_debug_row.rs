@@ -0,0 +1,47 @@
+use std::fmt;
+
+use crate::core::{AnchorDebugInfo, ObservedState};
+
+use super::RecalcState;
+
+/// The state of a single live node, as reported by `Engine::debug_rows`.
+#[derive(Copy, Clone, Debug)]
+pub struct NodeDebugRow {
+    pub debug_info: AnchorDebugInfo,
+    pub height: usize,
+    pub recalc_state: RecalcState,
+    pub observed_state: ObservedState,
+    /// Number of nodes that list this node as a necessary child.
+    pub necessary_count: usize,
+    /// Number of `Anchor`s pointing at this node.
+    pub handle_count: usize,
+    /// The generation this node was last polled as `Updated` or `Unchanged` in.
+    pub last_ready: Option<u64>,
+    /// The generation this node was last polled as `Updated` in.
+    pub last_update: Option<u64>,
+}
+
+impl NodeDebugRow {
+    pub(super) const HEADER: &'static str =
+        "height  recalc   observed     necessary  handles  ready  update  anchor";
+}
+
+impl fmt::Display for NodeDebugRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn generation(generation: Option<u64>) -> String {
+            generation.map_or_else(|| "-".to_string(), |generation| generation.to_string())
+        }
+        write!(
+            f,
+            "{:>6}  {:<7}  {:<11}  {:>9}  {:>7}  {:>5}  {:>6}  {}",
+            self.height,
+            format!("{:?}", self.recalc_state),
+            format!("{:?}", self.observed_state),
+            self.necessary_count,
+            self.handle_count,
+            generation(self.last_ready),
+            generation(self.last_update),
+            self.debug_info,
+        )
+    }
+}
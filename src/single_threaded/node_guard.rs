@@ -73,7 +73,6 @@ impl<'a> NodeGuard<'a> {
         }
     }
 
-    #[allow(dead_code)]
     pub(crate) fn necessary_children(self) -> impl Iterator<Item = NodeGuard<'a>> {
         RefCellVecIterator::new(
             self.0.node().ptrs.necessary_children.borrow_mut(),
@@ -21,6 +21,9 @@ pub(super) struct NodePtrs {
     pub(super) necessary_children: RefCell<Vec<NodePtr>>,
 
     pub(super) height: Cell<usize>,
+    /// The height of the recalc queue this node is in, if it's Pending. The node's height may
+    /// have been raised since it was queued.
+    pub(super) queued_height: Cell<usize>,
 
     pub(super) handle_count: Cell<usize>,
 }
@@ -0,0 +1,119 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use super::{graph, Anchor, Engine, Generation, NodeGuard, NodeKey, RecalcState};
+
+/// Keeps an anchor observed for as long as it's alive, and lets callbacks react to changes of
+/// its value.
+///
+/// Created by `Engine::observe`. Dropping it unobserves the anchor again, unless it's still
+/// observed through `Engine::mark_observed` or another `Observer`.
+pub struct Observer<T> {
+    anchor: Anchor<T>,
+    shared: Rc<ObserverShared<T>>,
+    still_alive: Rc<Cell<bool>>,
+}
+
+impl<T> Observer<T> {
+    pub(super) fn new(
+        anchor: Anchor<T>,
+        shared: Rc<ObserverShared<T>>,
+        still_alive: Rc<Cell<bool>>,
+    ) -> Self {
+        Self {
+            anchor,
+            shared,
+            still_alive,
+        }
+    }
+
+    /// Returns the observed anchor.
+    pub fn anchor(&self) -> &Anchor<T> {
+        &self.anchor
+    }
+
+    /// Returns the anchor's value as of the latest stabilization.
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        self.shared.value.borrow().clone()
+    }
+
+    /// Registers `callback` to be called with the old and new value whenever a stabilization
+    /// updated the anchor.
+    ///
+    /// After each stabilization, callbacks are called in the order their observers were created
+    /// in, and the callbacks of each observer in the order they were registered in.
+    pub fn on_update(&self, callback: impl FnMut(&T, &T) + 'static) {
+        self.shared.callbacks.borrow_mut().push(Box::new(callback));
+    }
+}
+
+impl<T> Drop for Observer<T> {
+    fn drop(&mut self) {
+        if self.still_alive.get() {
+            // `self.anchor` keeps the node from being freed
+            let node = NodeGuard(unsafe { self.shared.node_key.ptr.lookup_unchecked() });
+            node.observer_count.set(node.observer_count.get() - 1);
+            Engine::update_necessary_children(node);
+        }
+    }
+}
+
+type Callback<T> = Box<dyn FnMut(&T, &T)>;
+
+/// The part of an `Observer` the engine notifies after each stabilization.
+pub(super) trait ObserverTarget {
+    fn notify(&self, engine: &Engine);
+}
+
+pub(super) struct ObserverShared<T> {
+    node_key: NodeKey,
+    value: RefCell<T>,
+    last_update: Cell<Option<Generation>>,
+    callbacks: RefCell<Vec<Callback<T>>>,
+}
+
+impl<T> ObserverShared<T> {
+    pub(super) fn new(node_key: NodeKey, value: T, last_update: Option<Generation>) -> Self {
+        Self {
+            node_key,
+            value: RefCell::new(value),
+            last_update: Cell::new(last_update),
+            callbacks: RefCell::new(vec![]),
+        }
+    }
+}
+
+impl<T: 'static + Clone> ObserverTarget for ObserverShared<T> {
+    fn notify(&self, engine: &Engine) {
+        let new = engine.with(|graph| {
+            let node = graph.get(self.node_key)?;
+            let last_update = node.last_update.get();
+            if graph::recalc_state(node) != RecalcState::Ready
+                || last_update == self.last_update.get()
+            {
+                return None;
+            }
+            self.last_update.set(last_update);
+            Some(engine.cloned_output::<T>(node))
+        });
+        let new = match new {
+            Some(new) => new,
+            None => return,
+        };
+        let old = self.value.replace(new.clone());
+
+        // callbacks may register further callbacks, which only see later updates
+        let mut callbacks = std::mem::take(&mut *self.callbacks.borrow_mut());
+        for callback in &mut callbacks {
+            callback(&old, &new);
+        }
+        let registered = std::mem::take(&mut *self.callbacks.borrow_mut());
+        callbacks.extend(registered);
+        *self.callbacks.borrow_mut() = callbacks;
+    }
+}
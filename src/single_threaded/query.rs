@@ -0,0 +1,298 @@
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::HashMap,
+    hash::Hash,
+    panic::Location,
+    rc::{Rc, Weak},
+};
+
+use crate::core::{AnchorCore, Mount, OutputContext, Poll, UpdateContext};
+
+use super::{Anchor, AnchorKey, Engine, ObservedState};
+
+/// A function of some key whose results are memoized by an engine, in the style of
+/// [Salsa](https://crates.io/crates/salsa).
+///
+/// `Engine::query` returns the anchor computing a query for a key, creating it the first time
+/// it's asked for. The anchors and other queries `execute` reads through its `QueryContext` are
+/// its dependencies: it's executed again once one of them changed to a different value, and
+/// anchors depending on it are only recalculated if the value it returned differs from the
+/// previous one.
+///
+/// ```
+/// use anchors::single_threaded::*;
+///
+/// struct Double;
+///
+/// impl Query for Double {
+///     type Key = Anchor<usize>;
+///     type Value = usize;
+///
+///     fn execute(key: &Anchor<usize>, ctx: &mut impl QueryContext) -> Result<usize, Pending> {
+///         Ok(ctx.get(key)? * 2)
+///     }
+/// }
+///
+/// let mut engine = Engine::new();
+/// let num = Variable::new(1);
+/// let doubled = engine.query::<Double>(num.watch());
+/// assert_eq!(engine.get(&doubled), 2);
+///
+/// num.set(2);
+/// assert_eq!(engine.get(&doubled), 4);
+///
+/// // the anchor is memoized per key
+/// assert!(engine.query::<Double>(num.watch()) == doubled);
+/// ```
+pub trait Query: 'static {
+    type Key: 'static + Clone + Eq + Hash;
+    type Value: 'static + Clone + PartialEq;
+
+    /// Computes the value for `key`.
+    ///
+    /// If a dependency read through `ctx` isn't up-to-date yet, `ctx` returns `Err(Pending)`,
+    /// which `execute` should return as well, usually with `?`. It's called again once the
+    /// dependency was recalculated, so it should be free of side effects. Dependencies read
+    /// before returning are recalculated together, so reading all of them before returning
+    /// `Pending` spares calls.
+    fn execute(key: &Self::Key, ctx: &mut impl QueryContext) -> Result<Self::Value, Pending>;
+}
+
+/// The context passed to `Query::execute`, which tracks the anchors and queries read through
+/// it as dependencies.
+pub trait QueryContext {
+    /// Returns the value of `anchor`, or `Pending` if it has to be recalculated first.
+    fn get<O>(&mut self, anchor: &Anchor<O>) -> Result<O, Pending>
+    where
+        O: 'static + Clone;
+
+    /// Returns the value of query `Q` for `key`, or `Pending` if it has to be executed first.
+    #[track_caller]
+    fn query<Q: Query>(&mut self, key: &Q::Key) -> Result<Q::Value, Pending> {
+        let anchor = self.query_anchor::<Q>(key);
+        self.get(&anchor)
+    }
+
+    /// Returns the anchor of query `Q` for `key`, without depending on it.
+    #[track_caller]
+    fn query_anchor<Q: Query>(&mut self, key: &Q::Key) -> Anchor<Q::Value>;
+}
+
+/// Returned by `QueryContext` when a dependency of a query isn't up-to-date yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pending;
+
+/// The memoized query anchors of an engine, by query and key.
+#[derive(Default)]
+pub(super) struct Queries {
+    tables: HashMap<TypeId, Box<dyn QueryTable>>,
+}
+
+impl Queries {
+    /// Returns the anchor of query `Q` for `key`, mounting it onto the current engine if it
+    /// doesn't exist yet. `location` is where the query was first asked for.
+    pub(super) fn anchor<Q: Query>(
+        this: &Rc<RefCell<Self>>,
+        key: &Q::Key,
+        location: &'static Location<'static>,
+    ) -> Anchor<Q::Value> {
+        this.borrow_mut()
+            .table::<Q>()
+            .instances
+            .entry(key.clone())
+            .or_insert_with(|| {
+                Engine::mount(QueryAnchor::<Q> {
+                    key: key.clone(),
+                    queries: Rc::downgrade(this),
+                    location,
+                    deps: HashMap::new(),
+                    output: None,
+                    dirty: false,
+                    interrupted: false,
+                })
+            })
+            .clone()
+    }
+
+    /// Forgets all query anchors that are unnecessary, returning them so they can be dropped
+    /// once `self` isn't borrowed anymore.
+    pub(super) fn evict_unused(&mut self, engine: &Engine) -> Vec<Box<dyn Any>> {
+        self.tables
+            .values_mut()
+            .flat_map(|table| table.evict_unused(engine))
+            .collect()
+    }
+
+    fn table<Q: Query>(&mut self) -> &mut Instances<Q> {
+        self.tables
+            .entry(TypeId::of::<Q>())
+            .or_insert_with(|| {
+                Box::new(Instances::<Q> {
+                    instances: HashMap::new(),
+                })
+            })
+            .as_any_mut()
+            .downcast_mut()
+            .unwrap()
+    }
+}
+
+/// The instances of some query, with their type erased.
+trait QueryTable {
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    fn evict_unused(&mut self, engine: &Engine) -> Vec<Box<dyn Any>>;
+}
+
+struct Instances<Q: Query> {
+    instances: HashMap<Q::Key, Anchor<Q::Value>>,
+}
+
+impl<Q: Query> QueryTable for Instances<Q> {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn evict_unused(&mut self, engine: &Engine) -> Vec<Box<dyn Any>> {
+        let mut evicted: Vec<Box<dyn Any>> = vec![];
+        self.instances.retain(|_, anchor| {
+            let unused = engine.check_observed(anchor) == ObservedState::Unnecessary;
+            if unused {
+                evicted.push(Box::new(anchor.clone()));
+            }
+            !unused
+        });
+        evicted
+    }
+}
+
+/// The core of a query's anchor for one key.
+struct QueryAnchor<Q: Query> {
+    key: Q::Key,
+    queries: Weak<RefCell<Queries>>,
+    location: &'static Location<'static>,
+    // engines identify requested anchors by their key alone, so the output type is erased
+    deps: HashMap<AnchorKey, Anchor<()>>,
+    output: Option<Q::Value>,
+    // some dependency was marked as dirty since the last execution
+    dirty: bool,
+    // the last execution returned `Pending`, so `deps` may be incomplete
+    interrupted: bool,
+}
+
+impl<Q: Query> AnchorCore<Engine> for QueryAnchor<Q> {
+    type Output = Q::Value;
+
+    fn mark_dirty(&mut self, edge: AnchorKey) {
+        if self.deps.contains_key(&edge) {
+            self.dirty = true;
+        }
+    }
+
+    fn poll_updated(&mut self, ctx: &mut impl UpdateContext<Engine = Engine>) -> Poll {
+        if self.output.is_some() && !self.interrupted {
+            if !self.dirty {
+                return Poll::Unchanged;
+            }
+            // dirty dependencies may recalculate to the values they had before, which doesn't
+            // warrant executing again
+            let mut pending = false;
+            let mut changed = false;
+            for dep in self.deps.values() {
+                match ctx.request(dep, true) {
+                    Poll::Pending => pending = true,
+                    Poll::Updated => changed = true,
+                    Poll::Unchanged => {}
+                }
+            }
+            if pending {
+                return Poll::Pending;
+            }
+            self.dirty = false;
+            if !changed {
+                return Poll::Unchanged;
+            }
+        }
+
+        let mut run = QueryRun {
+            ctx,
+            queries: &self.queries,
+            deps: HashMap::new(),
+        };
+        let result = Q::execute(&self.key, &mut run);
+        let deps = run.deps;
+        match result {
+            Ok(value) => {
+                // dependencies that weren't read this time aren't dependencies anymore
+                for (key, dep) in &self.deps {
+                    if !deps.contains_key(key) {
+                        ctx.unrequest(dep);
+                    }
+                }
+                self.deps = deps;
+                self.dirty = false;
+                self.interrupted = false;
+                if self.output.as_ref() == Some(&value) {
+                    Poll::Unchanged
+                } else {
+                    self.output = Some(value);
+                    Poll::Updated
+                }
+            }
+            Err(Pending) => {
+                // stale dependencies can only be told apart after a complete run
+                self.deps.extend(deps);
+                self.interrupted = true;
+                Poll::Pending
+            }
+        }
+    }
+
+    fn output<'slf, 'out>(
+        &'slf self,
+        _ctx: &mut impl OutputContext<'out, Engine = Engine>,
+    ) -> &'out Self::Output
+    where
+        'slf: 'out,
+    {
+        self.output
+            .as_ref()
+            .expect("output called on a `query` that wasn't polled yet")
+    }
+
+    fn debug_location(&self) -> Option<(&'static str, &'static Location<'static>)> {
+        Some(("query", self.location))
+    }
+}
+
+/// The `QueryContext` of a single execution of a query.
+struct QueryRun<'a, C> {
+    ctx: &'a mut C,
+    queries: &'a Weak<RefCell<Queries>>,
+    deps: HashMap<AnchorKey, Anchor<()>>,
+}
+
+impl<'a, C: UpdateContext<Engine = Engine>> QueryContext for QueryRun<'a, C> {
+    fn get<O>(&mut self, anchor: &Anchor<O>) -> Result<O, Pending>
+    where
+        O: 'static + Clone,
+    {
+        self.deps
+            .entry(anchor.key())
+            .or_insert_with(|| Anchor::new_from_core(anchor.handle().clone()));
+        match self.ctx.request(anchor, true) {
+            Poll::Pending => Err(Pending),
+            _ => Ok(self.ctx.get(anchor).clone()),
+        }
+    }
+
+    #[track_caller]
+    fn query_anchor<Q: Query>(&mut self, key: &Q::Key) -> Anchor<Q::Value> {
+        let queries = self
+            .queries
+            .upgrade()
+            .expect("attempted to run a query of a dropped engine");
+        Queries::anchor::<Q>(&queries, key, Location::caller())
+    }
+}
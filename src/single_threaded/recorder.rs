@@ -0,0 +1,475 @@
+use std::{
+    cell::RefCell,
+    fmt::{self, Debug},
+    rc::Rc,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{graph, Anchor, AnchorKey, Engine, ObservedState, RecalcState, Variable};
+
+/// Records how an engine's inputs are driven into a `Log`, and replays logs onto other graphs.
+///
+/// Inputs and outputs are registered under keys, which identify them across graphs. While
+/// attached with `Engine::record`, the recorder logs the current values of registered
+/// variables, every value written to them as it's written, `mark_observed` and
+/// `mark_unobserved` calls on registered anchors, and every stabilization along with the
+/// outputs' values after it. Values are logged in serialized form, so the `Log` can be stored
+/// and replayed by a later process.
+///
+/// A variable can only be registered with one recorder at a time. Updates of registered
+/// variables are applied right away, so `Variable::update` clones their value if it's shared.
+///
+/// To reproduce a run, build the same graph again, register its variables and anchors with a
+/// fresh recorder under the same keys, and `replay` the log onto it.
+#[derive(Clone, Default)]
+pub struct Recorder {
+    shared: Rc<RefCell<RecorderShared>>,
+    // shared with the write hooks of registered variables, which may run on any thread
+    events: Arc<Mutex<Events>>,
+}
+
+#[derive(Default)]
+struct RecorderShared {
+    inputs: Vec<(String, Rc<dyn RecordedInput>)>,
+    outputs: Vec<(String, Rc<dyn RecordedOutput>)>,
+}
+
+#[derive(Default)]
+struct Events {
+    // whether the recorder is attached to an engine
+    recording: bool,
+    events: Vec<Event>,
+}
+
+impl Events {
+    fn push(&mut self, event: Event) {
+        if self.recording {
+            self.events.push(event);
+        }
+    }
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `variable` as an input under `key`.
+    ///
+    /// Panics if an input or output is already registered under `key`, or if a value written
+    /// to `variable` while recording can't be serialized.
+    pub fn input<T>(&self, key: impl Into<String>, variable: &Variable<T>)
+    where
+        T: 'static + Serialize + DeserializeOwned,
+    {
+        let key = self.check_key(key.into());
+        let input = Rc::new(Input {
+            variable: variable.clone(),
+        });
+        let events = Arc::downgrade(&self.events);
+        let hook_key = key.clone();
+        variable.on_write(Some(Box::new(move |value: &T| {
+            if let Some(events) = events.upgrade() {
+                lock(&events).push(Event::Write {
+                    key: hook_key.clone(),
+                    value: serialize(&hook_key, value),
+                });
+            }
+        })));
+        let value = input.value(&key);
+        lock(&self.events).push(Event::Write {
+            key: key.clone(),
+            value,
+        });
+        self.shared.borrow_mut().inputs.push((key, input));
+    }
+
+    /// Registers `anchor` as an output under `key`, whose values are compared when replaying.
+    ///
+    /// Panics if an input or output is already registered under `key`, or if its value can't
+    /// be serialized while recording.
+    pub fn output<T>(&self, key: impl Into<String>, anchor: &Anchor<T>)
+    where
+        T: 'static + Clone + PartialEq + Debug + Serialize + DeserializeOwned,
+    {
+        let key = self.check_key(key.into());
+        self.shared.borrow_mut().outputs.push((
+            key,
+            Rc::new(Output {
+                anchor: anchor.clone(),
+            }),
+        ));
+    }
+
+    /// Returns everything recorded so far.
+    pub fn log(&self) -> Log {
+        Log {
+            events: lock(&self.events).events.clone(),
+        }
+    }
+
+    /// Drives `engine` the same way the recorded engine was driven, using the inputs and
+    /// outputs registered with this recorder.
+    ///
+    /// Stops at the first stabilization after which a registered output's value differs from
+    /// the recorded one. Outputs that weren't up-to-date when recording aren't compared, and
+    /// neither are unobserved outputs that aren't up-to-date when replaying. If several outputs
+    /// diverged, the one that comes first in topological order is reported, since the others
+    /// may only have diverged because they depend on it.
+    ///
+    /// Panics if the log refers to a key that's not registered, or whose value can't be
+    /// deserialized into the registered variable's or anchor's type.
+    pub fn replay(&self, log: &Log, engine: &mut Engine) -> Result<(), Divergence> {
+        let mut stabilization = 0;
+        for event in &log.events {
+            match event {
+                Event::Write { key, value } => self.find_input(key).replay_write(key, value),
+                Event::Observe { key, observed } => match self.find_output(key) {
+                    Some(output) => output.set_observed(engine, *observed),
+                    None => self.find_input(key).set_observed(engine, *observed),
+                },
+                Event::Stabilize { outputs } => {
+                    engine.stabilize();
+                    let divergence = outputs
+                        .iter()
+                        .filter_map(|(key, expected)| {
+                            let output = self.find_output(key).unwrap_or_else(|| {
+                                panic!("no output is registered under recorded key `{}`", key)
+                            });
+                            let (expected, actual) = output.compare(engine, key, expected)?;
+                            Some((output.height(engine), key, expected, actual))
+                        })
+                        .min_by_key(|(height, ..)| *height);
+                    if let Some((_, key, expected, actual)) = divergence {
+                        return Err(Divergence {
+                            stabilization,
+                            key: key.clone(),
+                            expected,
+                            actual,
+                        });
+                    }
+                    stabilization += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts logging, beginning with the current values of all registered variables.
+    pub(super) fn start(&self) {
+        lock(&self.events).recording = true;
+        let inputs = self.shared.borrow().inputs.clone();
+        for (key, input) in inputs {
+            let value = input.value(&key);
+            lock(&self.events).push(Event::Write { key, value });
+        }
+    }
+
+    /// Stops logging until `start` is called again.
+    pub(super) fn stop(&self) {
+        lock(&self.events).recording = false;
+    }
+
+    /// Logs a `mark_observed` or `mark_unobserved` call, if it was made on a registered anchor.
+    pub(super) fn record_observed(&self, anchor_key: AnchorKey, observed: bool) {
+        let shared = self.shared.borrow();
+        let key = shared
+            .outputs
+            .iter()
+            .map(|(key, output)| (key, output.anchor_key()))
+            .chain(
+                shared
+                    .inputs
+                    .iter()
+                    .map(|(key, input)| (key, input.anchor_key())),
+            )
+            .find(|(_, key)| *key == anchor_key)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = key {
+            lock(&self.events).push(Event::Observe { key, observed });
+        }
+    }
+
+    /// Logs a stabilization, along with the values of all outputs that are up-to-date.
+    pub(super) fn record_stabilization(&self, engine: &Engine) {
+        let outputs = self.shared.borrow().outputs.clone();
+        let outputs = outputs
+            .into_iter()
+            .filter_map(|(key, output)| {
+                let value = output.value(engine, &key)?;
+                Some((key, value))
+            })
+            .collect();
+        lock(&self.events).push(Event::Stabilize { outputs });
+    }
+
+    fn check_key(&self, key: String) -> String {
+        let shared = self.shared.borrow();
+        let registered = shared.inputs.iter().map(|(key, _)| key);
+        if registered
+            .chain(shared.outputs.iter().map(|(key, _)| key))
+            .any(|registered| *registered == key)
+        {
+            panic!(
+                "something is already registered under recorder key `{}`",
+                key
+            );
+        }
+        key
+    }
+
+    fn find_input(&self, key: &str) -> Rc<dyn RecordedInput> {
+        let shared = self.shared.borrow();
+        let input = shared
+            .inputs
+            .iter()
+            .find(|(registered, _)| registered == key);
+        match input {
+            Some((_, input)) => Rc::clone(input),
+            None => panic!("no input is registered under recorded key `{}`", key),
+        }
+    }
+
+    fn find_output(&self, key: &str) -> Option<Rc<dyn RecordedOutput>> {
+        let shared = self.shared.borrow();
+        let output = shared
+            .outputs
+            .iter()
+            .find(|(registered, _)| registered == key);
+        output.map(|(_, output)| Rc::clone(output))
+    }
+}
+
+/// The events recorded by a `Recorder`, in the order they happened in.
+///
+/// It can be serialized with any serde format.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Log {
+    events: Vec<Event>,
+}
+
+impl Log {
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+impl Debug for Log {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.events).finish()
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+enum Event {
+    Write {
+        key: String,
+        value: serde_json::Value,
+    },
+    Observe {
+        key: String,
+        observed: bool,
+    },
+    Stabilize {
+        outputs: Vec<(String, serde_json::Value)>,
+    },
+}
+
+impl Debug for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Write { key, .. } => write!(f, "write {}", key),
+            Event::Observe {
+                key,
+                observed: true,
+            } => write!(f, "observe {}", key),
+            Event::Observe {
+                key,
+                observed: false,
+            } => write!(f, "unobserve {}", key),
+            Event::Stabilize { .. } => write!(f, "stabilize"),
+        }
+    }
+}
+
+/// The first difference `Recorder::replay` found between a recorded and a replayed output.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Divergence {
+    /// The index of the stabilization after which the output differed, counting from zero.
+    pub stabilization: usize,
+    /// The key the output is registered under.
+    pub key: String,
+    /// The recorded value, formatted with `Debug`.
+    pub expected: String,
+    /// The replayed value, formatted with `Debug`, unless the output wasn't up-to-date.
+    pub actual: Option<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "output `{}` diverged after stabilization {}: expected {}, ",
+            self.key, self.stabilization, self.expected
+        )?;
+        match &self.actual {
+            Some(actual) => write!(f, "got {}", actual),
+            None => write!(f, "but it wasn't up-to-date"),
+        }
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+trait RecordedInput {
+    fn anchor_key(&self) -> AnchorKey;
+
+    /// Returns the variable's current value, serialized.
+    fn value(&self, key: &str) -> serde_json::Value;
+
+    fn replay_write(&self, key: &str, value: &serde_json::Value);
+
+    fn set_observed(&self, engine: &mut Engine, observed: bool);
+}
+
+struct Input<T> {
+    variable: Variable<T>,
+}
+
+impl<T> RecordedInput for Input<T>
+where
+    T: 'static + Serialize + DeserializeOwned,
+{
+    fn anchor_key(&self) -> AnchorKey {
+        self.variable.watch().key()
+    }
+
+    fn value(&self, key: &str) -> serde_json::Value {
+        serialize(key, &*self.variable.get())
+    }
+
+    fn replay_write(&self, key: &str, value: &serde_json::Value) {
+        self.variable.replace(Arc::new(deserialize(key, value)));
+    }
+
+    fn set_observed(&self, engine: &mut Engine, observed: bool) {
+        set_observed(engine, &self.variable.watch(), observed)
+    }
+}
+
+trait RecordedOutput {
+    fn anchor_key(&self) -> AnchorKey;
+
+    /// Returns the anchor's value, serialized, if it's up-to-date.
+    fn value(&self, engine: &Engine, key: &str) -> Option<serde_json::Value>;
+
+    /// Returns the formatted expected and actual values, unless they're equal or the anchor
+    /// isn't up-to-date.
+    fn compare(
+        &self,
+        engine: &Engine,
+        key: &str,
+        expected: &serde_json::Value,
+    ) -> Option<(String, Option<String>)>;
+
+    /// Returns the anchor's height, which orders anchors topologically.
+    fn height(&self, engine: &Engine) -> usize;
+
+    fn set_observed(&self, engine: &mut Engine, observed: bool);
+}
+
+struct Output<T> {
+    anchor: Anchor<T>,
+}
+
+impl<T> Output<T>
+where
+    T: 'static + Clone,
+{
+    fn ready_value(&self, engine: &Engine) -> Option<T> {
+        engine.with(|graph| {
+            let node = graph.get(self.anchor.key().node_key)?;
+            if graph::recalc_state(node) != RecalcState::Ready {
+                return None;
+            }
+            Some(engine.cloned_output(node))
+        })
+    }
+}
+
+impl<T> RecordedOutput for Output<T>
+where
+    T: 'static + Clone + PartialEq + Debug + Serialize + DeserializeOwned,
+{
+    fn anchor_key(&self) -> AnchorKey {
+        self.anchor.key()
+    }
+
+    fn value(&self, engine: &Engine, key: &str) -> Option<serde_json::Value> {
+        Some(serialize(key, &self.ready_value(engine)?))
+    }
+
+    fn compare(
+        &self,
+        engine: &Engine,
+        key: &str,
+        expected: &serde_json::Value,
+    ) -> Option<(String, Option<String>)> {
+        let expected = deserialize::<T>(key, expected);
+        match self.ready_value(engine) {
+            Some(actual) if actual == expected => None,
+            Some(actual) => Some((format!("{:?}", expected), Some(format!("{:?}", actual)))),
+            // an output that's observed when recording should be up-to-date when replaying
+            None if engine.check_observed(&self.anchor) == ObservedState::Observed => {
+                Some((format!("{:?}", expected), None))
+            }
+            None => None,
+        }
+    }
+
+    fn height(&self, engine: &Engine) -> usize {
+        engine.with(|graph| {
+            graph
+                .get(self.anchor.key().node_key)
+                .map_or(0, graph::height)
+        })
+    }
+
+    fn set_observed(&self, engine: &mut Engine, observed: bool) {
+        set_observed(engine, &self.anchor, observed)
+    }
+}
+
+fn set_observed<T: 'static>(engine: &mut Engine, anchor: &Anchor<T>, observed: bool) {
+    if observed {
+        engine.mark_observed(anchor);
+    } else {
+        engine.mark_unobserved(anchor);
+    }
+}
+
+fn serialize<T: Serialize>(key: &str, value: &T) -> serde_json::Value {
+    serde_json::to_value(value)
+        .unwrap_or_else(|err| panic!("failed to serialize the value of `{}`: {}", key, err))
+}
+
+fn deserialize<T: DeserializeOwned>(key: &str, value: &serde_json::Value) -> T {
+    T::deserialize(value).unwrap_or_else(|err| {
+        panic!(
+            "failed to deserialize the recorded value of `{}`: {}",
+            key, err
+        )
+    })
+}
+
+// Events are consistent at all times, so a panic while they were locked can be ignored.
+fn lock(events: &Mutex<Events>) -> MutexGuard<'_, Events> {
+    events.lock().unwrap_or_else(PoisonError::into_inner)
+}
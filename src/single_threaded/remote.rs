@@ -0,0 +1,158 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Wake, Waker},
+    time::Duration,
+};
+
+/// Something that can pick up values written to it from other threads.
+pub(super) trait RemoteTarget {
+    /// Applies the most recent remote write, if there's one that wasn't superseded yet.
+    fn apply_remote(&self);
+
+    /// Returns whether the target can still receive writes.
+    fn is_alive(&self) -> bool;
+}
+
+/// Tracks the remote setters of an engine's variables.
+pub(super) struct Remote {
+    inbox: Arc<RemoteInbox>,
+    targets: RefCell<HashMap<usize, Box<dyn RemoteTarget>>>,
+    next_id: Cell<usize>,
+}
+
+impl Remote {
+    pub(super) fn new() -> Self {
+        Self {
+            inbox: Default::default(),
+            targets: Default::default(),
+            next_id: Cell::new(0),
+        }
+    }
+
+    pub(super) fn inbox(&self) -> &Arc<RemoteInbox> {
+        &self.inbox
+    }
+
+    /// Registers `target`, returning the id its remote setters should report writes with.
+    pub(super) fn register(&self, target: Box<dyn RemoteTarget>) -> usize {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.targets.borrow_mut().insert(id, target);
+        id
+    }
+
+    /// Returns a waker that calls `target.apply_remote()` when woken, from any thread.
+    pub(super) fn waker(&self, target: Box<dyn RemoteTarget>) -> Waker {
+        Waker::from(Arc::new(RemoteWaker {
+            id: self.register(target),
+            inbox: Arc::clone(&self.inbox),
+        }))
+    }
+
+    /// Applies all writes reported since the last call.
+    pub(super) fn apply_writes(&self) {
+        let written = std::mem::take(&mut *self.inbox.lock());
+        if written.is_empty() {
+            return;
+        }
+        let mut targets = self.targets.borrow_mut();
+        for id in written {
+            if let Some(target) = targets.get(&id) {
+                target.apply_remote();
+            }
+        }
+        // variables that have since been dropped won't receive any more writes
+        targets.retain(|_, target| target.is_alive());
+    }
+}
+
+struct RemoteWaker {
+    id: usize,
+    inbox: Arc<RemoteInbox>,
+}
+
+impl Wake for RemoteWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.inbox.push(self.id);
+    }
+}
+
+/// The thread-safe half of `Remote`, shared with every remote setter and waker.
+#[derive(Default)]
+pub(super) struct RemoteInbox {
+    written: Mutex<Vec<usize>>,
+    changed: Condvar,
+    // wakers of tasks awaiting `RemoteInbox::written`
+    tasks: Mutex<Vec<Waker>>,
+}
+
+impl RemoteInbox {
+    fn lock(&self) -> MutexGuard<'_, Vec<usize>> {
+        // the inbox is only ever pushed to or drained, so a panic can't leave it inconsistent
+        self.written.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(super) fn push(&self, id: usize) {
+        self.lock().push(id);
+        self.changed.notify_all();
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap_or_else(PoisonError::into_inner));
+        for task in tasks {
+            task.wake();
+        }
+    }
+
+    /// Blocks until some write is waiting in the inbox.
+    pub(super) fn wait_forever(&self) {
+        let _written = self
+            .changed
+            .wait_while(self.lock(), |written| written.is_empty())
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+
+    /// Resolves once some write is waiting in the inbox.
+    pub(super) fn written(&self) -> Written<'_> {
+        Written { inbox: self }
+    }
+
+    /// Blocks until some write is waiting in the inbox, or `timeout` has passed.
+    ///
+    /// Returns whether a write is waiting.
+    pub(super) fn wait(&self, timeout: Duration) -> bool {
+        let (written, _) = self
+            .changed
+            .wait_timeout_while(self.lock(), timeout, |written| written.is_empty())
+            .unwrap_or_else(PoisonError::into_inner);
+        !written.is_empty()
+    }
+}
+
+/// Future returned by `RemoteInbox::written`.
+pub(super) struct Written<'a> {
+    inbox: &'a RemoteInbox,
+}
+
+impl Future for Written<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // register before checking, so a write in between can't be missed
+        self.inbox
+            .tasks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(cx.waker().clone());
+        if self.inbox.lock().is_empty() {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+}
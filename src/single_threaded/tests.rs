@@ -1,33 +1,12 @@
-use crate::MultiAnchor;
+use crate::{
+    test_util::{block_on, oneshot},
+    MultiAnchor,
+};
 
 use super::Variable;
 
-#[test]
-fn test_cutoff_simple_observed() {
-    let mut engine = crate::single_threaded::Engine::new();
-    let (v, v_setter) = {
-        let var = Variable::new(100i32);
-        (var.watch(), var)
-    };
-    let mut old_val = 0i32;
-    let post_cutoff = v
-        .cutoff(move |new_val| {
-            if (old_val - *new_val).abs() < 50 {
-                false
-            } else {
-                old_val = *new_val;
-                true
-            }
-        })
-        .map(|v| *v + 10);
-    engine.mark_observed(&post_cutoff);
-    assert_eq!(engine.get(&post_cutoff), 110);
-    v_setter.set(125);
-    assert_eq!(engine.get(&post_cutoff), 110);
-    v_setter.set(151);
-    assert_eq!(engine.get(&post_cutoff), 161);
-    v_setter.set(125);
-    assert_eq!(engine.get(&post_cutoff), 161);
+mod shared {
+    include!("../test_util/engine_tests.rs");
 }
 
 #[test]
@@ -75,121 +54,6 @@ fn test_refmap_simple() {
     assert!(engine.get(&b_correct));
 }
 
-#[test]
-fn test_split_simple() {
-    let mut engine = crate::single_threaded::Engine::new();
-    let (v, _) = {
-        let var = Variable::new((1usize, 2usize, 3usize));
-        (var.watch(), var)
-    };
-    let (a, b, c) = v.split();
-    assert_eq!(engine.get(&a), 1);
-    assert_eq!(engine.get(&b), 2);
-    assert_eq!(engine.get(&c), 3);
-}
-
-#[test]
-fn test_map_simple() {
-    let mut engine = crate::single_threaded::Engine::new();
-    let (v1, _v1_setter) = {
-        let var = Variable::new(1usize);
-        (var.watch(), var)
-    };
-    let (v2, _v2_setter) = {
-        let var = Variable::new(123usize);
-        (var.watch(), var)
-    };
-    let _a2 = v1.map(|num1| {
-        println!("a: adding to {:?}", num1);
-        *num1
-    });
-    let a = MultiAnchor::map((&v1, &v2), |num1, num2| num1 + num2);
-
-    let b = MultiAnchor::map((&v1, &a, &v2), |num1, num2, num3| num1 + num2 + num3);
-    engine.mark_observed(&b);
-    engine.stabilize();
-    assert_eq!(engine.get(&b), 248);
-}
-
-#[test]
-fn test_then_simple() {
-    let mut engine = crate::single_threaded::Engine::new();
-    let (v1, v1_setter) = {
-        let var = Variable::new(true);
-        (var.watch(), var)
-    };
-    let (v2, _v2_setter) = {
-        let var = Variable::new(10usize);
-        (var.watch(), var)
-    };
-    let (v3, _v3_setter) = {
-        let var = Variable::new(20usize);
-        (var.watch(), var)
-    };
-    let a = v1.then(move |val| if *val { v2.clone() } else { v3.clone() });
-    engine.mark_observed(&a);
-    engine.stabilize();
-    assert_eq!(engine.get(&a), 10);
-
-    v1_setter.set(false);
-    engine.stabilize();
-    assert_eq!(engine.get(&a), 20);
-}
-
-#[test]
-fn test_observed_marking() {
-    use crate::single_threaded::ObservedState;
-
-    let mut engine = crate::single_threaded::Engine::new();
-    let (v1, _v1_setter) = {
-        let var = Variable::new(1usize);
-        (var.watch(), var)
-    };
-    let a = v1.map(|num1| *num1 + 1);
-    let b = a.map(|num1| *num1 + 2);
-    let c = b.map(|num1| *num1 + 3);
-    engine.mark_observed(&a);
-    engine.mark_observed(&c);
-
-    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&v1));
-    assert_eq!(ObservedState::Observed, engine.check_observed(&a));
-    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&b));
-    assert_eq!(ObservedState::Observed, engine.check_observed(&c));
-
-    engine.stabilize();
-
-    assert_eq!(ObservedState::Necessary, engine.check_observed(&v1));
-    assert_eq!(ObservedState::Observed, engine.check_observed(&a));
-    assert_eq!(ObservedState::Necessary, engine.check_observed(&b));
-    assert_eq!(ObservedState::Observed, engine.check_observed(&c));
-
-    engine.mark_unobserved(&c);
-
-    assert_eq!(ObservedState::Necessary, engine.check_observed(&v1));
-    assert_eq!(ObservedState::Observed, engine.check_observed(&a));
-    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&b));
-    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&c));
-
-    engine.mark_unobserved(&a);
-
-    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&v1));
-    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&a));
-    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&b));
-    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&c));
-}
-
-#[test]
-fn test_garbage_collection_wont_panic() {
-    let mut engine = crate::single_threaded::Engine::new();
-    let (v1, _v1_setter) = {
-        let var = Variable::new(1usize);
-        (var.watch(), var)
-    };
-    engine.get(&v1);
-    std::mem::drop(v1);
-    engine.stabilize();
-}
-
 #[test]
 fn test_readme_example() {
     // example
@@ -251,3 +115,771 @@ fn test_readme_example() {
     my_unread_updater.set(50);
     assert_eq!(engine.get(&dynamic_name), "Robo");
 }
+
+#[test]
+fn test_variable_setter_from_other_thread() {
+    let mut engine = crate::single_threaded::Engine::new();
+    let var = Variable::new(1usize);
+    let doubled = var.watch().map(|n| n * 2);
+    engine.mark_observed(&doubled);
+    assert_eq!(engine.get(&doubled), 2);
+
+    let setter = var.setter();
+    std::thread::spawn(move || setter.set(21)).join().unwrap();
+    assert_eq!(engine.get(&doubled), 42);
+    assert_eq!(*var.get(), 21);
+
+    // local writes supersede remote writes that weren't picked up yet
+    var.setter().set(5);
+    var.set(6);
+    assert_eq!(engine.get(&doubled), 12);
+}
+
+#[test]
+fn test_wait_for_changes() {
+    use std::time::Duration;
+
+    let mut engine = crate::single_threaded::Engine::new();
+    let var = Variable::new(1usize);
+    assert!(!engine.wait_for_changes(Duration::from_millis(10)));
+
+    let setter = var.setter();
+    let handle = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(10));
+        setter.set(2);
+    });
+    assert!(engine.wait_for_changes(Duration::from_secs(10)));
+    handle.join().unwrap();
+    assert_eq!(engine.get(&var.watch()), 2);
+    assert!(!engine.wait_for_changes(Duration::ZERO));
+}
+
+#[test]
+fn test_from_future_get_async() {
+    use crate::single_threaded::Anchor;
+
+    let mut engine = crate::single_threaded::Engine::new();
+    let (send, receive) = oneshot();
+    let doubled = Anchor::from_future(receive).map(|n: &usize| n * 2);
+    engine.mark_observed(&doubled);
+    engine.stabilize();
+
+    let handle = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        send(21);
+    });
+    assert_eq!(block_on(engine.get_async(&doubled)), 42);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_try_get_reports_height_exceeded() {
+    use crate::{
+        single_threaded::{Anchor, Engine},
+        Error,
+    };
+
+    let mut engine = Engine::new_with_max_height(4);
+    let tall = (0..4).fold(Anchor::constant(0usize), |anchor, _| anchor.map(|n| n + 1));
+    assert!(matches!(
+        engine.try_get(&tall),
+        Err(Error::HeightExceeded { max_height: 4, .. })
+    ));
+}
+
+#[test]
+fn test_try_observe_reports_errors() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use crate::{
+        single_threaded::{Anchor, Engine},
+        Error,
+    };
+
+    let mut engine = Engine::new();
+    let broken = Anchor::constant(1usize).map(|_| -> usize { panic!("broken formula") });
+    let fine = Anchor::constant(2usize);
+    let foreign = {
+        let _other = Engine::new();
+        Anchor::constant(3usize)
+    };
+    let err = Err(Error::ForeignAnchor { requested_by: None });
+    assert_eq!(engine.try_observe(&foreign).map(|_| ()), err);
+    assert_eq!(engine.try_to_dot_for(&foreign).map(|_| ()), err);
+
+    // poisoning is permanent
+    assert!(catch_unwind(AssertUnwindSafe(|| engine.get(&broken))).is_err());
+    assert_eq!(engine.try_observe(&fine).map(|_| ()), Err(Error::Poisoned));
+    assert_eq!(engine.try_mark_unobserved(&fine), Err(Error::Poisoned));
+    assert_eq!(engine.try_stabilize(), Err(Error::Poisoned));
+}
+
+#[test]
+fn test_deep_chain_without_max_height() {
+    let mut engine = crate::single_threaded::Engine::new();
+    let var = Variable::new(0usize);
+    let deep = (0..1000).fold(var.watch(), |anchor, _| anchor.map(|n| n + 1));
+    assert_eq!(engine.get(&deep), 1000);
+
+    engine.shrink_to_fit();
+    var.set(1);
+    assert_eq!(engine.get(&deep), 1001);
+}
+
+#[test]
+fn test_debug_rows() {
+    use crate::single_threaded::{Engine, ObservedState, RecalcState};
+
+    let mut engine = Engine::new();
+    let var = Variable::new(1);
+    let doubled = var.watch().map(|n| n * 2);
+    let unused = var.watch().map(|n| n + 1);
+    engine.mark_observed(&doubled);
+    assert_eq!(engine.get(&doubled), 2);
+
+    let rows = engine.debug_rows();
+    assert_eq!(rows.len(), 3);
+    let (var_row, doubled_row, unused_row) = (&rows[0], &rows[1], &rows[2]);
+
+    assert_eq!(var_row.height, 0);
+    assert_eq!(var_row.recalc_state, RecalcState::Ready);
+    assert_eq!(var_row.observed_state, ObservedState::Necessary);
+    assert_eq!(var_row.necessary_count, 1);
+    assert!(var_row.last_update.is_some());
+    assert_eq!(var_row.last_update, var_row.last_ready);
+
+    assert_eq!(doubled_row.height, 1);
+    assert_eq!(doubled_row.recalc_state, RecalcState::Ready);
+    assert_eq!(doubled_row.observed_state, ObservedState::Observed);
+    assert_eq!(doubled_row.debug_info.location().unwrap().0, "map");
+    assert_eq!(doubled_row.handle_count, 1);
+
+    assert_eq!(unused_row.recalc_state, RecalcState::Needed);
+    assert_eq!(unused_row.observed_state, ObservedState::Unnecessary);
+    assert_eq!(unused_row.last_ready, None);
+
+    // freed nodes are left out
+    std::mem::drop(unused);
+    assert_eq!(engine.debug_rows().len(), 2);
+    assert_eq!(engine.debug_state().lines().count(), 3);
+}
+
+#[test]
+fn test_to_dot() {
+    let mut engine = crate::single_threaded::Engine::new();
+    let a = Variable::new(1);
+    let b = Variable::new(2);
+    let sum = (&a.watch(), &b.watch()).map(|a, b| a + b);
+    let unrelated = b.watch().map(|b| b * 2);
+    engine.mark_observed(&sum);
+    assert_eq!(engine.get(&sum), 3);
+
+    let dot = engine.to_dot();
+    assert!(dot.starts_with("digraph anchors {\n"));
+    assert_eq!(dot.matches("[label=").count(), 4);
+    assert_eq!(dot.matches("fillcolor=gold").count(), 1);
+    // both inputs of `sum` are clean parents and necessary children of it
+    assert_eq!(dot.matches("[style=solid]").count(), 2);
+    assert_eq!(dot.matches("[style=dashed]").count(), 2);
+
+    let dot = engine.to_dot_for(&sum);
+    assert_eq!(dot.matches("[label=").count(), 3);
+
+    let dot = engine.to_dot_for(&unrelated);
+    assert_eq!(dot.matches("[label=").count(), 1);
+    assert!(dot.contains("color=red"));
+}
+
+#[test]
+fn test_observer_callbacks() {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::single_threaded::{Engine, ObservedState};
+
+    let mut engine = Engine::new();
+    let var = Variable::new(1);
+    let doubled = var.watch().map(|n| n * 2);
+    let parity = var.watch().map(|n| n % 2);
+
+    let calls = Rc::new(RefCell::new(vec![]));
+    let doubled_observer = engine.observe(&doubled);
+    let parity_observer = engine.observe(&parity);
+    assert_eq!(doubled_observer.get(), 2);
+    assert_eq!(engine.check_observed(&doubled), ObservedState::Observed);
+
+    for (name, observer) in [("doubled", &doubled_observer), ("parity", &parity_observer)] {
+        let calls = Rc::clone(&calls);
+        observer.on_update(move |old, new| calls.borrow_mut().push((name, *old, *new)));
+    }
+    let second_calls = Rc::clone(&calls);
+    doubled_observer.on_update(move |_, _| second_calls.borrow_mut().push(("second", 0, 0)));
+
+    // the parity didn't change, so its callback isn't called
+    var.set(3);
+    engine.stabilize();
+    assert_eq!(*calls.borrow(), vec![("doubled", 2, 6), ("second", 0, 0)]);
+    assert_eq!(doubled_observer.get(), 6);
+
+    calls.borrow_mut().clear();
+    var.set(2);
+    engine.stabilize();
+    assert_eq!(
+        *calls.borrow(),
+        vec![("doubled", 6, 4), ("second", 0, 0), ("parity", 1, 0)]
+    );
+
+    calls.borrow_mut().clear();
+    engine.stabilize();
+    assert!(calls.borrow().is_empty());
+
+    std::mem::drop(doubled_observer);
+    assert_eq!(engine.check_observed(&doubled), ObservedState::Unnecessary);
+    var.set(5);
+    engine.stabilize();
+    assert_eq!(*calls.borrow(), vec![("parity", 0, 1)]);
+}
+
+#[test]
+fn test_variable_update_and_set_if_changed() {
+    use std::{cell::Cell, rc::Rc};
+
+    let mut engine = crate::single_threaded::Engine::new();
+    let var = Variable::new(vec![1, 2, 3]);
+    let recalculations = Rc::new(Cell::new(0));
+    let len = {
+        let recalculations = Rc::clone(&recalculations);
+        var.watch().map(move |values| {
+            recalculations.set(recalculations.get() + 1);
+            values.len()
+        })
+    };
+    engine.mark_observed(&len);
+    assert_eq!(engine.get(&len), 3);
+
+    var.update(|values| {
+        values.push(4);
+        true
+    });
+    assert_eq!(engine.get(&len), 4);
+    assert_eq!(recalculations.get(), 2);
+
+    // reporting no change doesn't propagate the mutation
+    var.update(|values| {
+        values[0] = 0;
+        false
+    });
+    assert_eq!(*var.get(), vec![0, 2, 3, 4]);
+    assert_eq!(engine.get(&len), 4);
+    assert_eq!(recalculations.get(), 2);
+
+    assert!(!var.set_if_changed(vec![0, 2, 3, 4]));
+    assert_eq!(engine.get(&len), 4);
+    assert_eq!(recalculations.get(), 2);
+
+    assert!(var.set_if_changed(vec![5]));
+    assert_eq!(engine.get(&len), 1);
+    assert_eq!(recalculations.get(), 3);
+
+    assert!(!var.set_if_changed_by(vec![6], |old, new| old.len() == new.len()));
+    assert_eq!(*var.get(), vec![5]);
+}
+
+#[test]
+#[should_panic(expected = "attempted to move the clock backwards")]
+fn test_clock_backwards() {
+    let engine = crate::single_threaded::Engine::new();
+    let clock = engine.clock();
+    let start = clock.current();
+    clock.advance_by(std::time::Duration::from_secs(1));
+    clock.advance_to(start);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_restore_is_undoable() {
+    let mut engine = crate::single_threaded::Engine::new();
+    let count = Variable::new(1);
+    engine.register_variable("count", &count);
+    let doubled = count.watch().map(|count| count * 2);
+    engine.mark_observed(&doubled);
+    assert_eq!(engine.get(&doubled), 2);
+
+    // restoring is recorded like a transaction
+    engine.enable_history(1);
+    let snapshot = serde_json::from_str(r#"{"count":4}"#).unwrap();
+    engine.restore(&snapshot).unwrap();
+    assert_eq!(engine.get(&doubled), 8);
+    assert!(engine.undo());
+    assert_eq!(engine.get(&doubled), 2);
+}
+
+#[test]
+fn test_undo_redo() {
+    let mut engine = crate::single_threaded::Engine::new();
+    let a = Variable::new(1);
+    let b = Variable::new(2);
+    let sum = (&a.watch(), &b.watch()).map(|a, b| a + b);
+    engine.mark_observed(&sum);
+    assert!(!engine.undo());
+
+    engine.enable_history(2);
+    // plain writes aren't recorded
+    a.set(1);
+    assert!(!engine.can_undo());
+    engine.transaction(|tx| {
+        tx.set(&a, 10);
+        tx.set(&b, 20);
+    });
+    engine.transaction(|tx| tx.set(&a, 100));
+    // empty transactions aren't recorded
+    engine.transaction(|_| {});
+    assert_eq!(engine.get(&sum), 120);
+
+    assert!(engine.undo());
+    assert_eq!(engine.get(&sum), 30);
+    assert!(engine.undo());
+    assert_eq!(engine.get(&sum), 3);
+    assert!(!engine.undo());
+
+    assert!(engine.redo());
+    assert_eq!(engine.get(&sum), 30);
+    assert!(engine.can_redo());
+
+    // a new batch discards what could have been redone
+    engine.transaction(|tx| tx.set(&b, 5));
+    assert!(!engine.can_redo());
+    assert_eq!(engine.get(&sum), 15);
+
+    // only the latest two batches are kept
+    engine.transaction(|tx| tx.set(&b, 6));
+    assert!(engine.undo());
+    assert!(engine.undo());
+    assert!(!engine.undo());
+    assert_eq!(engine.get(&sum), 30);
+
+    engine.disable_history();
+    assert!(!engine.can_redo());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_record_and_replay() {
+    use crate::single_threaded::{Anchor, Divergence, Log, Recorder};
+
+    fn build(buggy: bool) -> (Variable<i32>, Anchor<i32>, Recorder) {
+        let input = Variable::new(1);
+        let output = input
+            .watch()
+            .map(move |value| if buggy && *value > 10 { 0 } else { value * 2 });
+        let quadrupled = output.map(|value| value * 2);
+        let recorder = Recorder::new();
+        recorder.input("input", &input);
+        // depends on `output`, so it's compared after it even though it's registered first
+        recorder.output("quadrupled", &quadrupled);
+        recorder.output("output", &output);
+        (input, quadrupled, recorder)
+    }
+
+    let mut engine = crate::single_threaded::Engine::new();
+    let (input, quadrupled, recorder) = build(false);
+    // writes aren't logged until the recorder is attached
+    input.set(3);
+    engine.record(&recorder);
+    engine.stabilize();
+    engine.mark_observed(&quadrupled);
+    input.set(5);
+    input.update(|value| {
+        *value *= 4;
+        true
+    });
+    engine.stabilize();
+    // unregistered anchors aren't logged
+    engine.mark_observed(&input.watch().map(|value| value + 1));
+    engine.stabilize();
+    input.set(30);
+    engine.stop_recording();
+    input.set(40);
+    engine.stabilize();
+
+    let log = recorder.log();
+    assert_eq!(
+        format!("{:?}", log),
+        "[write input, stabilize, observe quadrupled, write input, write input, stabilize, \
+         stabilize, write input]"
+    );
+    let log: Log = serde_json::from_str(&serde_json::to_string(&log).unwrap()).unwrap();
+    assert_eq!(log, recorder.log());
+
+    let mut engine = crate::single_threaded::Engine::new();
+    let (_, _, replayed) = build(false);
+    assert_eq!(replayed.replay(&log, &mut engine), Ok(()));
+
+    let mut engine = crate::single_threaded::Engine::new();
+    let (_, _, replayed) = build(true);
+    assert_eq!(
+        replayed.replay(&log, &mut engine),
+        Err(Divergence {
+            stabilization: 1,
+            key: "output".to_string(),
+            expected: "40".to_string(),
+            actual: Some("0".to_string()),
+        })
+    );
+}
+
+#[test]
+fn test_pausable_resumes_on_pending_input() {
+    let mut engine = crate::single_threaded::Engine::new();
+    let a = Variable::new(1);
+    let b = Variable::new(0);
+    let input = (&a.watch(), &b.watch()).map(|a, _| *a);
+    let hidden = Variable::new(false);
+    let shown = input.pausable(&hidden.watch());
+    engine.mark_observed(&shown);
+    assert_eq!(engine.get(&shown), 1);
+
+    hidden.set(true);
+    engine.stabilize();
+    engine.mark_observed(&input);
+    a.set(2);
+    engine.stabilize();
+    engine.mark_unobserved(&input);
+    hidden.set(true);
+    engine.stabilize();
+
+    // the input is stale once resumed, and recalculates to the value it had while paused
+    b.set(1);
+    hidden.set(false);
+    assert_eq!(engine.get(&shown), 2);
+}
+
+#[test]
+fn test_then_scoped_invalidates_previous_branches() {
+    use crate::{single_threaded::Anchor, Error};
+    use std::{cell::Cell, cell::RefCell, rc::Rc};
+
+    let mut engine = crate::single_threaded::Engine::new();
+    let switch = Variable::new(0);
+    let input = Variable::new(1);
+    let recalculations = Rc::new(Cell::new(0));
+    let built: Rc<RefCell<Vec<Anchor<i32>>>> = Rc::default();
+    let result = {
+        let input = input.watch();
+        let recalculations = Rc::clone(&recalculations);
+        let built = Rc::clone(&built);
+        switch.watch().then_scoped(move |switch| {
+            let switch = *switch;
+            let recalculations = Rc::clone(&recalculations);
+            let branch = input.map(move |input| {
+                recalculations.set(recalculations.get() + 1);
+                input + switch
+            });
+            built.borrow_mut().push(branch.clone());
+            branch
+        })
+    };
+    engine.mark_observed(&result);
+    assert_eq!(engine.get(&result), 1);
+    let live_nodes = engine.debug_rows().len();
+
+    for i in 1..=3 {
+        switch.set(i);
+        assert_eq!(engine.get(&result), 1 + i);
+    }
+    assert_eq!(engine.debug_rows().len(), live_nodes);
+
+    let stale = built.borrow()[0].clone();
+    assert!(matches!(
+        engine.try_get(&stale),
+        Err(Error::Invalidated { .. })
+    ));
+
+    // only the current branch is recalculated
+    input.set(10);
+    assert_eq!(engine.get(&result), 13);
+    assert_eq!(recalculations.get(), 5);
+}
+
+#[test]
+fn test_then_keeps_cached_branches() {
+    use crate::single_threaded::Anchor;
+    use std::collections::HashMap;
+
+    let mut engine = crate::single_threaded::Engine::new();
+    let switch = Variable::new(0);
+    let input = Variable::new(1);
+    let result = {
+        let input = input.watch();
+        let mut branches: HashMap<i32, Anchor<i32>> = HashMap::new();
+        switch.watch().then(move |switch| {
+            let switch = *switch;
+            branches
+                .entry(switch)
+                .or_insert_with(|| input.map(move |input| input + switch))
+                .clone()
+        })
+    };
+    engine.mark_observed(&result);
+    assert_eq!(engine.get(&result), 1);
+    switch.set(1);
+    assert_eq!(engine.get(&result), 2);
+
+    // switching back returns the branch built by the first call
+    switch.set(0);
+    assert_eq!(engine.try_get(&result), Ok(1));
+    input.set(10);
+    assert_eq!(engine.get(&result), 10);
+}
+
+#[test]
+fn test_query() {
+    use crate::single_threaded::{Anchor, Pending, Query, QueryContext};
+    use std::cell::Cell;
+
+    thread_local! {
+        static EXECUTIONS: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+    }
+
+    // executions abandoned because of pending dependencies are counted too
+    fn take_executions() -> (usize, usize) {
+        EXECUTIONS.with(|executions| executions.replace((0, 0)))
+    }
+
+    struct LineCount;
+
+    impl Query for LineCount {
+        type Key = Anchor<String>;
+        type Value = usize;
+
+        fn execute(file: &Anchor<String>, ctx: &mut impl QueryContext) -> Result<usize, Pending> {
+            EXECUTIONS.with(|executions| {
+                let (lines, total) = executions.get();
+                executions.set((lines + 1, total));
+            });
+            Ok(ctx.get(file)?.lines().count())
+        }
+    }
+
+    struct TotalLines;
+
+    impl Query for TotalLines {
+        type Key = (Anchor<bool>, Vec<Anchor<String>>);
+        type Value = usize;
+
+        fn execute(
+            (all, files): &(Anchor<bool>, Vec<Anchor<String>>),
+            ctx: &mut impl QueryContext,
+        ) -> Result<usize, Pending> {
+            EXECUTIONS.with(|executions| {
+                let (lines, total) = executions.get();
+                executions.set((lines, total + 1));
+            });
+            let files = if ctx.get(all)? {
+                &files[..]
+            } else {
+                &files[..1]
+            };
+            files.iter().map(|file| ctx.query::<LineCount>(file)).sum()
+        }
+    }
+
+    let mut engine = crate::single_threaded::Engine::new();
+    let all = Variable::new(true);
+    let a = Variable::new("a\nb".to_string());
+    let b = Variable::new("c".to_string());
+    let key = (all.watch(), vec![a.watch(), b.watch()]);
+    let (total, line) = (engine.query::<TotalLines>(key.clone()), line!());
+    assert!(engine.query::<TotalLines>(key) == total);
+    let row = engine.debug_rows().pop().unwrap();
+    let (name, location) = row.debug_info.location().unwrap();
+    assert_eq!(name, "query");
+    assert_eq!((location.file(), location.line()), (file!(), line));
+    engine.mark_observed(&total);
+    assert_eq!(engine.get(&total), 3);
+    take_executions();
+    // queries asked for by other queries point at the `execute` asking for them
+    for row in engine.debug_rows() {
+        if let Some(("query", location)) = row.debug_info.location() {
+            assert_eq!(location.file(), file!());
+        }
+    }
+
+    // only the changed file is counted again
+    a.set("a".to_string());
+    assert_eq!(engine.get(&total), 2);
+    assert_eq!(take_executions(), (1, 1));
+
+    // the total isn't executed again if no line count changed
+    b.set("d".to_string());
+    assert_eq!(engine.get(&total), 2);
+    assert_eq!(take_executions(), (1, 0));
+
+    // files that aren't read anymore aren't dependencies anymore
+    all.set(false);
+    assert_eq!(engine.get(&total), 1);
+    b.set("e\nf".to_string());
+    assert_eq!(engine.get(&total), 1);
+    take_executions();
+    b.set("g".to_string());
+    assert_eq!(engine.get(&total), 1);
+    assert_eq!(take_executions(), (0, 0));
+
+    // only instances nothing observed depends on are evicted
+    let count_a = engine.query::<LineCount>(a.watch());
+    assert_eq!(engine.evict_unused_queries(), 1);
+    assert!(engine.query::<LineCount>(a.watch()) == count_a);
+    engine.mark_unobserved(&total);
+    assert_eq!(engine.evict_unused_queries(), 2);
+    assert!(engine.query::<LineCount>(a.watch()) != count_a);
+    assert_eq!(engine.get(&count_a), 1);
+}
+
+#[test]
+fn test_query_reexecutes_only_for_changed_dependencies() {
+    use crate::single_threaded::{Anchor, Pending, Query, QueryContext};
+    use std::cell::Cell;
+
+    thread_local! {
+        static EXECUTIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn take_executions() -> usize {
+        EXECUTIONS.with(|executions| executions.replace(0))
+    }
+
+    struct Sum;
+
+    impl Query for Sum {
+        type Key = Vec<Anchor<usize>>;
+        type Value = usize;
+
+        fn execute(
+            nums: &Vec<Anchor<usize>>,
+            ctx: &mut impl QueryContext,
+        ) -> Result<usize, Pending> {
+            EXECUTIONS.with(|executions| executions.set(executions.get() + 1));
+            // reading every number before bailing gets them all recalculated together
+            let nums: Vec<_> = nums.iter().map(|num| ctx.get(num)).collect();
+            nums.into_iter().sum()
+        }
+    }
+
+    let mut engine = crate::single_threaded::Engine::new();
+    let vars: Vec<_> = (0..100).map(Variable::new).collect();
+    let parities: Vec<_> = vars
+        .iter()
+        .map(|var| var.watch().map(|num| num % 2))
+        .collect();
+    let sum = engine.query::<Sum>(parities);
+    engine.mark_observed(&sum);
+    assert_eq!(engine.get(&sum), 50);
+    assert_eq!(take_executions(), 2);
+
+    // none of the parities changed
+    for var in &vars {
+        var.set(*var.get() + 2);
+    }
+    assert_eq!(engine.get(&sum), 50);
+    assert_eq!(take_executions(), 0);
+
+    for var in &vars {
+        var.set(*var.get() + 1);
+    }
+    assert_eq!(engine.get(&sum), 50);
+    assert_eq!(take_executions(), 1);
+
+    vars[0].set(4);
+    assert_eq!(engine.get(&sum), 49);
+    assert_eq!(take_executions(), 1);
+}
+
+#[test]
+fn test_variable_update_mutates_in_place() {
+    use std::{cell::Cell, rc::Rc};
+
+    struct Counted(Rc<Cell<usize>>, usize);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            self.0.set(self.0.get() + 1);
+            Counted(Rc::clone(&self.0), self.1)
+        }
+    }
+
+    let mut engine = crate::single_threaded::Engine::new();
+    let clones = Rc::new(Cell::new(0));
+    let var = Variable::new(Counted(Rc::clone(&clones), 0));
+    let watched = var.watch().map(|counted| counted.1);
+    engine.mark_observed(&watched);
+    assert_eq!(engine.get(&watched), 0);
+
+    // updates wait for the anchor to be recalculated, which mutates its value in place
+    for i in 1..=3 {
+        var.update(|counted| {
+            counted.1 += 1;
+            true
+        });
+        var.update(|counted| {
+            counted.1 += 1;
+            true
+        });
+        assert_eq!(engine.get(&watched), 2 * i);
+    }
+    assert_eq!(clones.get(), 0);
+
+    // reading the value before that clones it once, since the anchor still outputs it
+    var.update(|counted| {
+        counted.1 += 1;
+        true
+    });
+    assert_eq!(var.get().1, 7);
+    assert_eq!(clones.get(), 1);
+    assert_eq!(engine.get(&watched), 7);
+    assert_eq!(clones.get(), 1);
+}
+
+#[test]
+fn test_fork_leaves_engine_untouched() {
+    use std::{cell::Cell, rc::Rc};
+
+    let mut engine = crate::single_threaded::Engine::new();
+    let price = Variable::new(10);
+    let quantity = Variable::new(3);
+    let total = (&price.watch(), &quantity.watch()).map(|price, quantity| price * quantity);
+    engine.mark_observed(&total);
+    assert_eq!(engine.get(&total), 30);
+    let generation = engine.current_generation();
+    let last_changed = engine.last_changed(&total);
+
+    let calculations = Rc::new(Cell::new(0));
+    let mut fork = engine.fork(|fork| {
+        let calculations = calculations.clone();
+        let fork_price = fork.variable(&price);
+        assert!(fork.variable(&price).watch() == fork_price.watch());
+        let doubled = fork.variable(&quantity).watch().map(move |quantity| {
+            calculations.set(calculations.get() + 1);
+            quantity * 2
+        });
+        (&fork_price.watch(), &doubled).map(|price, doubled| price * doubled)
+    });
+    let fork_total = fork.outputs().clone();
+    assert_eq!(fork.get(&fork_total), 60);
+
+    // overriding the price doesn't recalculate anchors that don't depend on it
+    for (price_override, expected) in [(12, 72), (5, 30)] {
+        fork.set(&price, price_override);
+        assert_eq!(fork.get(&fork_total), expected);
+    }
+    assert_eq!(calculations.get(), 1);
+
+    // reset copies pick up the engine's current value
+    price.set(20);
+    fork.reset(&price);
+    assert_eq!(fork.get(&fork_total), 120);
+    drop(fork);
+
+    assert_eq!(*quantity.get(), 3);
+    assert_eq!(engine.current_generation(), generation);
+    assert_eq!(engine.last_changed(&total), last_changed);
+    assert_eq!(engine.get(&total), 60);
+}
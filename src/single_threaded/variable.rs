@@ -1,119 +1,76 @@
-use std::{cell::RefCell, panic::Location, rc::Rc};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
 
-use crate::core::{AnchorCore, DirtyHandle as _, Engine as _, OutputContext, Poll, UpdateContext};
+use crate::core::VarShared;
 
-use super::{Anchor, AnchorHandle, DirtyHandle, Engine};
+use super::{Engine, RemoteInbox, RemoteTarget};
 
 /// A variable that exposes an anchor for its value.
-pub struct Variable<T> {
-    inner: Rc<RefCell<VarShared<T>>>,
-    anchor: Anchor<T>,
-}
-
-impl<T> Clone for Variable<T> {
-    fn clone(&self) -> Self {
-        Self {
-            inner: Rc::clone(&self.inner),
-            anchor: self.anchor.clone(),
-        }
-    }
-}
+pub type Variable<T> = crate::core::Variable<T, Engine>;
 
 impl<T> Variable<T>
 where
-    T: 'static,
+    T: 'static + Send,
 {
-    /// Creates a new variable
-    #[track_caller]
-    pub fn new(value: T) -> Variable<T> {
-        let value = Rc::new(value);
-        let inner = Rc::new(RefCell::new(VarShared {
-            dirty_handle: None,
-            value: Rc::clone(&value),
-            value_changed: true,
-        }));
-        Variable {
-            inner: Rc::clone(&inner),
-            anchor: Engine::mount(VarAnchor {
-                inner,
-                value,
-                location: Location::caller(),
-            }),
+    /// Returns a handle for setting this variable from other threads.
+    ///
+    /// Values set through it are picked up by the next stabilization of the engine, or by
+    /// `Variable::get`, whichever comes first. Use `Engine::wait_for_changes` to block until
+    /// that happens.
+    pub fn setter(&self) -> VariableSetter<T> {
+        let anchor = self.watch();
+        let graph = anchor
+            .handle()
+            .graph()
+            .expect("attempted to create a setter for a variable whose engine was dropped");
+        VariableSetter {
+            id: graph.remote.register(Box::new(self.downgrade())),
+            value: self.remote_value(),
+            inbox: Arc::clone(graph.remote.inbox()),
         }
     }
+}
 
-    /// Updates the value inside the VarAnchor, and indicates to the recomputation graph that
-    /// the value has changed.
-    pub fn set(&self, value: T) {
-        let mut inner = self.inner.borrow_mut();
-        inner.value = Rc::new(value);
-        if let Some(waker) = &inner.dirty_handle {
-            waker.mark_dirty();
-        }
-        inner.value_changed = true;
-    }
-
-    /// Retrieves the last value set
-    pub fn get(&self) -> Rc<T> {
-        Rc::clone(&self.inner.borrow().value)
-    }
+/// A `Send` handle for setting a `Variable` from another thread.
+///
+/// Created by `Variable::setter`.
+pub struct VariableSetter<T> {
+    id: usize,
+    value: Arc<Mutex<Option<T>>>,
+    inbox: Arc<RemoteInbox>,
+}
 
-    pub fn watch(&self) -> Anchor<T> {
-        self.anchor.clone()
+impl<T> Clone for VariableSetter<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            value: Arc::clone(&self.value),
+            inbox: Arc::clone(&self.inbox),
+        }
     }
 }
 
-#[derive(Clone)]
-struct VarShared<T> {
-    dirty_handle: Option<DirtyHandle>,
-    value: Rc<T>,
-    value_changed: bool,
+impl<T> VariableSetter<T> {
+    /// Queues a new value for the variable, replacing any value queued before that hasn't been
+    /// picked up yet, and wakes up `Engine::wait_for_changes`.
+    pub fn set(&self, value: T) {
+        *lock(&self.value) = Some(value);
+        self.inbox.push(self.id);
+    }
 }
 
-/// An Anchor type for values that are mutated by calling a setter function from outside of the Anchors recomputation graph.
-struct VarAnchor<T> {
-    inner: Rc<RefCell<VarShared<T>>>,
-    value: Rc<T>,
-    location: &'static Location<'static>,
+fn lock<T>(value: &Mutex<T>) -> MutexGuard<'_, T> {
+    // values are only ever replaced or taken, so a panic can't leave them inconsistent
+    value.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
-impl<T> AnchorCore<Engine> for VarAnchor<T>
-where
-    T: 'static,
-{
-    type Output = T;
-
-    fn mark_dirty(&mut self, _edge: <AnchorHandle as crate::core::AnchorHandle>::AnchorKey) {
-        panic!("attempt to mark a variable's non-existent inputs as as dirty")
-    }
-
-    fn poll_updated(&mut self, ctx: &mut impl UpdateContext<Engine = Engine>) -> Poll {
-        let mut inner = self.inner.borrow_mut();
-        let first_update = inner.dirty_handle.is_none();
-        if first_update {
-            inner.dirty_handle = Some(ctx.dirty_handle());
+impl<T> RemoteTarget for Weak<Mutex<VarShared<T, Engine>>> {
+    fn apply_remote(&self) {
+        if let Some(inner) = self.upgrade() {
+            lock(&inner).apply_remote();
         }
-        let res = if inner.value_changed {
-            self.value = Rc::clone(&inner.value);
-            Poll::Updated
-        } else {
-            Poll::Unchanged
-        };
-        inner.value_changed = false;
-        res
-    }
-
-    fn output<'slf, 'out>(
-        &'slf self,
-        _ctx: &mut impl OutputContext<'out, Engine = Engine>,
-    ) -> &'out Self::Output
-    where
-        'slf: 'out,
-    {
-        &self.value
     }
 
-    fn debug_location(&self) -> Option<(&'static str, &'static Location<'static>)> {
-        Some(("Variable", self.location))
+    fn is_alive(&self) -> bool {
+        self.strong_count() > 0
     }
 }
@@ -0,0 +1,61 @@
+//! A thread-safe execution engine.
+//!
+//! It mirrors the `single_threaded` engine, but keeps its recomputation graph behind
+//! `Arc`s and mutexes instead of `Rc`s and cells. Any `Anchor<T, sync::Engine>` is `Send + Sync`
+//! whenever `T` is, which means anchors and variables may be sent to, set and dropped on any
+//! thread. New anchors are mounted onto the engine most recently created on the current thread.
+//!
+//! Cores mounted on this engine (including the closures passed to `map`, `then` and friends)
+//! must be `Send + Sync`.
+
+use std::{
+    cell::RefCell,
+    sync::{Arc, Weak},
+    task::Wake,
+};
+
+mod anchor;
+mod anchor_handle;
+mod clock;
+mod constant;
+mod context;
+mod context_mut;
+mod engine;
+mod graph;
+mod node_key;
+mod variable;
+mod workers;
+
+pub use self::{anchor::*, anchor_handle::*, clock::*, constant::*, engine::*, variable::*};
+
+pub use crate::core::{BeforeOrAfter, Generation, ObservedState};
+
+use self::{context::*, context_mut::*, graph::*, node_key::*, workers::*};
+
+thread_local! {
+    static DEFAULT_MOUNTER: RefCell<Option<Arc<Graph>>> = const { RefCell::new(None) };
+}
+
+/// A handle, which allows an anchor with non-Anchors inputs to manually mark itself as dirty.
+#[derive(Clone, Debug)]
+pub struct DirtyHandle {
+    key: NodeKey,
+    graph: Weak<Graph>,
+}
+
+impl crate::core::DirtyHandle for DirtyHandle {
+    fn mark_dirty(&self) {
+        if let Some(graph) = self.graph.upgrade() {
+            graph.push_dirty_mark(self.key);
+        }
+    }
+}
+
+impl Wake for DirtyHandle {
+    fn wake(self: Arc<Self>) {
+        crate::core::DirtyHandle::mark_dirty(&*self);
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,52 @@
+use std::any::Any;
+
+use crate::core::{AnchorCore, AnchorDebugInfo, Poll};
+
+use super::{AnchorKey, Engine, EngineContext, EngineContextMut};
+
+/// The main struct of the Anchors library.
+///
+/// Represents a single value on the `sync` recomputation graph.
+///
+/// You should basically never need to create these with `Anchor::new_from_core`;
+/// instead call functions like `Variable::new`, `Constant::new` and `MultiAnchor::map` to create them.
+pub type Anchor<T> = crate::Anchor<T, Engine>;
+
+pub(super) trait GenericAnchor: Send + Sync {
+    fn mark_dirty(&mut self, child_key: AnchorKey);
+
+    fn poll_updated(&mut self, ctx: &mut EngineContextMut<'_>) -> Poll;
+
+    fn output<'slf, 'out>(&'slf self, ctx: &mut EngineContext<'out>) -> &'out dyn Any
+    where
+        'slf: 'out;
+
+    fn debug_info(&self) -> AnchorDebugInfo;
+}
+
+impl<I> GenericAnchor for I
+where
+    I: 'static + AnchorCore<Engine> + Send + Sync,
+{
+    fn mark_dirty(&mut self, child_key: AnchorKey) {
+        AnchorCore::mark_dirty(self, child_key)
+    }
+
+    fn poll_updated(&mut self, ctx: &mut EngineContextMut<'_>) -> Poll {
+        AnchorCore::poll_updated(self, ctx)
+    }
+
+    fn output<'slf, 'out>(&'slf self, ctx: &mut EngineContext<'out>) -> &'out dyn Any
+    where
+        'slf: 'out,
+    {
+        AnchorCore::output(self, ctx)
+    }
+
+    fn debug_info(&self) -> AnchorDebugInfo {
+        AnchorDebugInfo {
+            location: self.debug_location(),
+            type_info: std::any::type_name::<I>(),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::{Arc, Weak};
+
+use super::{Graph, NodeKey};
+
+/// A key uniquely identifying a handle within a computational graph.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct AnchorKey {
+    pub(super) node_key: NodeKey,
+}
+
+impl AnchorKey {
+    pub(super) fn new(node_key: NodeKey) -> Self {
+        Self { node_key }
+    }
+}
+
+/// The handle of an anchor from a thread-safe computation graph.
+///
+/// Once the last handle pointing to a node is dropped, the node is queued for removal
+/// and freed the next time the engine touches its graph.
+#[derive(Clone, Debug)]
+pub struct AnchorHandle {
+    inner: Arc<HandleInner>,
+}
+
+#[derive(Debug)]
+struct HandleInner {
+    node_key: NodeKey,
+    graph: Weak<Graph>,
+}
+
+impl AnchorHandle {
+    pub(super) fn new(node_key: NodeKey, graph: Weak<Graph>) -> Self {
+        Self {
+            inner: Arc::new(HandleInner { node_key, graph }),
+        }
+    }
+}
+
+impl Drop for HandleInner {
+    fn drop(&mut self) {
+        if let Some(graph) = self.graph.upgrade() {
+            graph.inbox().dropped.push(self.node_key);
+        }
+    }
+}
+
+impl crate::core::AnchorHandle for AnchorHandle {
+    type AnchorKey = AnchorKey;
+
+    fn key(&self) -> Self::AnchorKey {
+        AnchorKey::new(self.inner.node_key)
+    }
+}
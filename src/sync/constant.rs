@@ -0,0 +1,4 @@
+use super::Engine;
+
+/// A constant that exposes an anchor for its value.
+pub type Constant<T> = crate::core::Constant<T, Engine>;
@@ -0,0 +1,35 @@
+use crate::core::OutputContext;
+
+use super::{Anchor, Engine, GraphState, RecalcState};
+
+pub(super) struct EngineContext<'eng> {
+    state: &'eng GraphState,
+}
+
+impl<'eng> EngineContext<'eng> {
+    pub(super) fn new(state: &'eng GraphState) -> Self {
+        Self { state }
+    }
+}
+
+impl<'eng> OutputContext<'eng> for EngineContext<'eng> {
+    type Engine = Engine;
+
+    fn get<'out, O>(&self, anchor: &Anchor<O>) -> &'out O
+    where
+        'eng: 'out,
+        O: 'static,
+    {
+        let state = self.state;
+        let node = state.node(anchor.key().node_key).unwrap();
+        if node.recalc_state != RecalcState::Ready {
+            panic!("attempted to get node that was not previously requested")
+        }
+        node.anchor
+            .as_ref()
+            .expect("attempted to get node that is currently being polled")
+            .output(&mut EngineContext { state })
+            .downcast_ref()
+            .unwrap()
+    }
+}
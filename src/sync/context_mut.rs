@@ -0,0 +1,178 @@
+use std::{sync::Arc, task::Waker};
+
+use crate::{
+    core::{OutputContext, Poll, UpdateContext},
+    Error,
+};
+
+use super::{
+    Anchor, DirtyHandle, Engine, EngineContext, Graph, GraphState, NodeKey, ObservedState,
+    RecalcState,
+};
+
+/// The context passed to an `AnchorCore` being polled by the thread-safe engine.
+///
+/// The graph is only borrowed immutably while polling, so any change to the graph requested
+/// by the polled core is recorded as an `Op` and applied once polling finished.
+pub(super) struct EngineContextMut<'eng> {
+    graph: &'eng Arc<Graph>,
+    state: &'eng GraphState,
+    node: NodeKey,
+    ops: Vec<Op>,
+    pending_on_anchor_get: bool,
+    error: Option<Error>,
+}
+
+/// A change to the graph requested by a polled node.
+#[derive(Copy, Clone, Debug)]
+pub(super) enum Op {
+    /// Makes sure the polled node is higher than the child.
+    EnsureHeight(NodeKey),
+    QueueRecalc(NodeKey),
+    AddCleanParent(NodeKey),
+    AddNecessaryChild(NodeKey),
+    Unrequest(NodeKey),
+}
+
+impl<'eng> EngineContextMut<'eng> {
+    pub(super) fn new(graph: &'eng Arc<Graph>, state: &'eng GraphState, node: NodeKey) -> Self {
+        Self {
+            graph,
+            state,
+            node,
+            ops: vec![],
+            pending_on_anchor_get: false,
+            error: None,
+        }
+    }
+
+    pub(super) fn finish(self, poll: Poll) -> Polled {
+        Polled {
+            poll,
+            ops: self.ops,
+            pending_on_anchor_get: self.pending_on_anchor_get,
+            error: self.error,
+        }
+    }
+
+    /// Records `error`, and reports the requested anchor as pending so the polled node bails.
+    fn fail(&mut self, error: Error) -> Poll {
+        self.error.get_or_insert(error);
+        Poll::Pending
+    }
+}
+
+/// The outcome of polling a node, to be applied to the graph once polling finished.
+pub(super) struct Polled {
+    pub(super) poll: Poll,
+    pub(super) ops: Vec<Op>,
+    pub(super) pending_on_anchor_get: bool,
+    pub(super) error: Option<Error>,
+}
+
+impl Op {
+    pub(super) fn apply(self, state: &mut GraphState, node: NodeKey) -> Result<(), Error> {
+        match self {
+            Op::EnsureHeight(child) => {
+                state.ensure_height_increases(child, node)?;
+            }
+            Op::QueueRecalc(child) => state.queue_recalc(child),
+            Op::AddCleanParent(child) => state.add_clean_parent(child, node),
+            Op::AddNecessaryChild(child) => state.add_necessary_child(node, child),
+            Op::Unrequest(child) => {
+                state.remove_necessary_child(node, child);
+                state.update_necessary_children(child);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UpdateContext for EngineContextMut<'_> {
+    type Engine = Engine;
+
+    fn get<'out, 'slf, O>(&'slf self, anchor: &Anchor<O>) -> &'out O
+    where
+        'slf: 'out,
+        O: 'static,
+    {
+        EngineContext::new(self.state).get(anchor)
+    }
+
+    fn request<'out, O>(&mut self, anchor: &Anchor<O>, necessary: bool) -> Poll
+    where
+        O: 'static,
+    {
+        let child_key = anchor.key().node_key;
+        let this = self.state.node(self.node).unwrap();
+        if !self.graph.accepts_key(child_key) {
+            return self.fail(Error::ForeignAnchor {
+                requested_by: Some(this.debug_info.to_string()),
+            });
+        }
+        // anchors mounted while polling only enter the graph once polling finished
+        let child = self.state.node(child_key);
+
+        let height_already_increased = child.map_or(false, |child| child.height < this.height);
+        if !height_already_increased {
+            self.ops.push(Op::EnsureHeight(child_key));
+        }
+
+        let necessary =
+            necessary && self.state.check_observed(self.node) != ObservedState::Unnecessary;
+
+        match child {
+            Some(child) if child.recalc_state == RecalcState::Waiting => {
+                // recalculating won't help until the child is woken up; once it finished, it
+                // marks us as dirty like any other clean parent
+                self.ops.push(Op::AddCleanParent(child_key));
+                if necessary {
+                    self.ops.push(Op::AddNecessaryChild(child_key));
+                }
+                Poll::Pending
+            }
+            Some(child) if child.recalc_state == RecalcState::Ready => {
+                if !height_already_increased {
+                    self.pending_on_anchor_get = true;
+                    return Poll::Pending;
+                }
+                self.ops.push(Op::AddCleanParent(child_key));
+                if necessary {
+                    self.ops.push(Op::AddNecessaryChild(child_key));
+                }
+                match (child.last_update, this.last_ready) {
+                    (Some(a), Some(b)) if a <= b => Poll::Unchanged,
+                    _ => Poll::Updated,
+                }
+            }
+            _ => {
+                self.pending_on_anchor_get = true;
+                self.ops.push(Op::QueueRecalc(child_key));
+                // not clean yet, but tracking the edge lets height updates find cycles through it
+                self.ops.push(Op::AddCleanParent(child_key));
+                if necessary {
+                    self.ops.push(Op::AddNecessaryChild(child_key));
+                }
+                Poll::Pending
+            }
+        }
+    }
+
+    fn unrequest<'out, O>(&mut self, anchor: &Anchor<O>)
+    where
+        O: 'static,
+    {
+        self.ops.push(Op::Unrequest(anchor.key().node_key));
+    }
+
+    fn dirty_handle(&mut self) -> DirtyHandle {
+        DirtyHandle {
+            key: self.node,
+            graph: Arc::downgrade(self.graph),
+        }
+    }
+
+    fn waker(&mut self) -> Waker {
+        Waker::from(Arc::new(self.dirty_handle()))
+    }
+}
@@ -0,0 +1,602 @@
+use std::{
+    marker::PhantomData,
+    panic,
+    sync::{Arc, Mutex, PoisonError},
+    time::Instant,
+};
+
+use crate::{
+    core::{AnchorCore, Mount, OutputContext, Poll, Transaction},
+    Error,
+};
+
+use super::{
+    Anchor, AnchorHandle, Clock, DirtyHandle, EngineContext, EngineContextMut, Generation,
+    GenericAnchor, Graph, GraphState, NodeKey, ObservedState, Polled, RecalcState, WorkerPool,
+    DEFAULT_MOUNTER,
+};
+
+// batches with fewer nodes than this are polled on the calling thread, since handing them to
+// the workers costs more than polling them
+const MIN_PARALLEL_BATCH: usize = 4;
+
+/// An engine for thread-safe execution of a computation graph.
+pub struct Engine {
+    graph: Arc<Graph>,
+
+    // threads used to recalculate nodes sharing a height, if there's more than one
+    workers: Option<WorkerPool>,
+
+    // tracks the current stabilization generation; incremented on every stabilize
+    generation: Generation,
+
+    // created the first time it's asked for
+    clock: Mutex<Option<Clock>>,
+
+    // variables included in snapshots, by key
+    #[cfg(feature = "serde")]
+    snapshots: crate::core::SnapshotRegistry<dyn crate::core::SnapshotEntry<Engine> + Send + Sync>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl crate::core::Engine for Engine {
+    type AnchorHandle = AnchorHandle;
+    type DirtyHandle = DirtyHandle;
+}
+
+impl crate::core::Runtime for Engine {
+    fn try_mark_observed<O>(&mut self, anchor: &Anchor<O>) -> Result<(), Error>
+    where
+        O: 'static,
+    {
+        Engine::try_mark_observed(self, anchor)
+    }
+
+    fn try_mark_unobserved<O>(&mut self, anchor: &Anchor<O>) -> Result<(), Error>
+    where
+        O: 'static,
+    {
+        Engine::try_mark_unobserved(self, anchor)
+    }
+
+    fn check_observed<O>(&self, anchor: &Anchor<O>) -> ObservedState {
+        Engine::check_observed(self, anchor)
+    }
+
+    fn try_get<O>(&mut self, anchor: &Anchor<O>) -> Result<O, Error>
+    where
+        O: 'static + Clone,
+    {
+        Engine::try_get(self, anchor)
+    }
+
+    fn try_stabilize(&mut self) -> Result<(), Error> {
+        Engine::try_stabilize(self)
+    }
+}
+
+impl<I> Mount<I> for Engine
+where
+    I: 'static + AnchorCore<Self> + Send + Sync,
+{
+    fn mount(inner: I) -> Anchor<I::Output> {
+        DEFAULT_MOUNTER.with(|default_mounter| {
+            let borrow = default_mounter.borrow();
+            let graph = borrow
+                .as_ref()
+                .expect("no engine was initialized. did you call `Engine::new()`?");
+            Anchor::new_from_core(graph.insert(Box::new(inner)))
+        })
+    }
+}
+
+impl Engine {
+    /// Creates a new Engine, which recalculates nodes one at a time on the calling thread.
+    ///
+    /// Anchors created on the current thread from now on will be mounted onto this engine. Use
+    /// `Engine::enter` to switch between several engines.
+    pub fn new() -> Self {
+        Self::new_with_workers(1)
+    }
+
+    /// Creates a new Engine, which recalculates nodes sharing a height in parallel on up to
+    /// `workers` threads.
+    ///
+    /// Nodes of the same height never depend on each other, so each height's queued nodes are
+    /// polled concurrently; the changes they make to the graph are then applied in the same
+    /// order the sequential engine would have, before moving on to the next height. Outputs are
+    /// identical to those of a sequential engine. The worker threads are spawned once and live
+    /// as long as the engine, but handing nodes to them only pays off for wide graphs of
+    /// expensive nodes, so small batches are polled on the calling thread.
+    pub fn new_with_workers(workers: usize) -> Self {
+        let graph = Arc::new(Graph::new());
+        DEFAULT_MOUNTER.with(|v| *v.borrow_mut() = Some(Arc::clone(&graph)));
+        Self {
+            graph,
+            workers: if workers > 1 {
+                Some(WorkerPool::new(workers))
+            } else {
+                None
+            },
+            generation: Generation::new(),
+            clock: Mutex::new(None),
+            #[cfg(feature = "serde")]
+            snapshots: Default::default(),
+        }
+    }
+
+    /// Runs `f` with this as the engine new anchors created on the current thread are mounted
+    /// onto, making the previously current engine current again afterwards.
+    ///
+    /// This allows using several engines side by side on one thread.
+    pub fn enter<R>(&self, f: impl FnOnce() -> R) -> R {
+        let _guard = self.enter_guard();
+        f()
+    }
+
+    /// Makes this the engine new anchors created on the current thread are mounted onto, until
+    /// the returned guard is dropped.
+    pub fn enter_guard(&self) -> EnterGuard {
+        let previous = DEFAULT_MOUNTER.with(|v| v.replace(Some(Arc::clone(&self.graph))));
+        EnterGuard {
+            previous,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Returns the engine's virtual clock, creating it the first time it's asked for.
+    ///
+    /// Its time starts out as the moment it was created, and only moves when it's advanced.
+    pub fn clock(&self) -> Clock {
+        self.clock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert_with(|| self.enter(|| Clock::new(Instant::now())))
+            .clone()
+    }
+
+    /// Runs `f` with a `Transaction`, whose staged variable writes are applied all at once
+    /// after `f` returned, unless they were rolled back.
+    ///
+    /// See `Runtime::transaction`.
+    pub fn transaction<R>(&mut self, f: impl FnOnce(&mut Transaction<Self>) -> R) -> R {
+        crate::core::Runtime::transaction(self, f)
+    }
+
+    /// Marks an Anchor as observed. All observed nodes will always be brought up-to-date
+    /// when *any* Anchor in the graph is retrieved. If you get an output value fairly
+    /// often, it's best to mark it as Observed so that Anchors can calculate its
+    /// dependencies faster.
+    pub fn mark_observed<O>(&mut self, anchor: &Anchor<O>)
+    where
+        O: 'static,
+    {
+        self.try_mark_observed(anchor)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Marks an Anchor as observed like `Engine::mark_observed`, but reports misuse as an
+    /// error instead of panicking.
+    pub fn try_mark_observed<O>(&mut self, anchor: &Anchor<O>) -> Result<(), Error>
+    where
+        O: 'static,
+    {
+        let mut state = self.graph.lock()?;
+        let node_key = anchor.key().node_key;
+        let node = state
+            .node_mut(node_key)
+            .ok_or(Error::ForeignAnchor { requested_by: None })?;
+        node.observed = true;
+        if node.recalc_state != RecalcState::Ready {
+            state.queue_recalc(node_key);
+        }
+        Ok(())
+    }
+
+    /// Marks an Anchor as unobserved. If the `anchor` has parents that are necessary
+    /// because `anchor` was previously observed, those parents will be unmarked as
+    /// necessary.
+    pub fn mark_unobserved<O>(&mut self, anchor: &Anchor<O>)
+    where
+        O: 'static,
+    {
+        self.try_mark_unobserved(anchor)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Marks an Anchor as unobserved like `Engine::mark_unobserved`, but reports misuse as an
+    /// error instead of panicking.
+    pub fn try_mark_unobserved<O>(&mut self, anchor: &Anchor<O>) -> Result<(), Error>
+    where
+        O: 'static,
+    {
+        let mut state = self.graph.lock()?;
+        let node_key = anchor.key().node_key;
+        state
+            .node_mut(node_key)
+            .ok_or(Error::ForeignAnchor { requested_by: None })?
+            .observed = false;
+        state.update_necessary_children(node_key);
+        Ok(())
+    }
+
+    /// Retrieves the value of an Anchor, recalculating dependencies as necessary to get the
+    /// latest value.
+    ///
+    /// If some dependency is waiting for a future, this blocks the current thread until all of
+    /// them resolved.
+    pub fn get<O>(&mut self, anchor: &Anchor<O>) -> O
+    where
+        O: 'static + Clone,
+    {
+        self.try_get(anchor).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Retrieves the value of an Anchor like `Engine::get`, but reports misuse of the engine,
+    /// such as cyclic anchors, as an error instead of panicking.
+    pub fn try_get<O>(&mut self, anchor: &Anchor<O>) -> Result<O, Error>
+    where
+        O: 'static + Clone,
+    {
+        loop {
+            if let Some(output) = self.get_ready(anchor)? {
+                return Ok(output);
+            }
+            // futures wake their anchors by marking them as dirty
+            self.graph.wait_for_dirty_marks();
+        }
+    }
+
+    /// Retrieves the value of an Anchor like `Engine::get`, but yields to the executor
+    /// instead of blocking while some dependency is waiting for a future.
+    pub async fn get_async<O>(&mut self, anchor: &Anchor<O>) -> O
+    where
+        O: 'static + Clone,
+    {
+        self.try_get_async(anchor)
+            .await
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Retrieves the value of an Anchor like `Engine::get_async`, but reports misuse of the
+    /// engine as an error instead of panicking.
+    pub async fn try_get_async<O>(&mut self, anchor: &Anchor<O>) -> Result<O, Error>
+    where
+        O: 'static + Clone,
+    {
+        loop {
+            if let Some(output) = self.get_ready(anchor)? {
+                return Ok(output);
+            }
+            self.graph.dirtied().await;
+        }
+    }
+
+    /// Brings `anchor` up-to-date and returns its value, unless some dependency is still
+    /// waiting for a future.
+    fn get_ready<O>(&mut self, anchor: &Anchor<O>) -> Result<Option<O>, Error>
+    where
+        O: 'static + Clone,
+    {
+        // stabilize once before, since the stabilization process may mark our requested node
+        // as dirty
+        self.try_stabilize()?;
+        let mut state = self.graph.lock()?;
+        let node_key = anchor.key().node_key;
+        let recalc_state = state
+            .node(node_key)
+            .ok_or(Error::ForeignAnchor { requested_by: None })?
+            .recalc_state;
+        if recalc_state != RecalcState::Ready {
+            state.queue_recalc(node_key);
+            // stabilize again, to make sure our target node that is now in the queue is up-to-date
+            self.stabilize0(&mut state)?;
+        }
+        if state.node(node_key).unwrap().recalc_state != RecalcState::Ready {
+            return Ok(None);
+        }
+        Ok(Some(EngineContext::new(&state).get(anchor).clone()))
+    }
+
+    fn update_dirty_marks(&self, state: &mut GraphState) {
+        for dirty in self.graph.take_dirty_marks() {
+            state.mark_dirty(dirty, false);
+        }
+    }
+
+    /// Ensure any Observed nodes are up-to-date, recalculating dependencies as necessary. You
+    /// should rarely need to call this yourself; `Engine::get` calls it automatically.
+    pub fn stabilize(&mut self) {
+        self.try_stabilize().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Ensure any Observed nodes are up-to-date like `Engine::stabilize`, but reports misuse
+    /// of the engine, such as cyclic anchors, as an error instead of panicking.
+    ///
+    /// Stabilization stops at the first error, leaving the remaining nodes queued for the
+    /// next one.
+    pub fn try_stabilize(&mut self) -> Result<(), Error> {
+        let mut state = self.graph.lock()?;
+        self.update_dirty_marks(&mut state);
+        self.generation.increment();
+        self.stabilize0(&mut state)
+    }
+
+    /// internal function for stabilization. does not update dirty marks or increment the stabilization number
+    fn stabilize0(&self, state: &mut GraphState) -> Result<(), Error> {
+        // anchors created by `then` closures belong to this engine, whichever engine was
+        // created last on this thread
+        let _mounter = self.enter_guard();
+        if let Some(workers) = &self.workers {
+            return self.stabilize0_parallel(state, workers);
+        }
+        while let Some((height, node_key)) = state.recalc_pop_next() {
+            let calculation_complete = if state.node(node_key).unwrap().height == height {
+                // this nodes height is current, so we can recalculate
+                self.recalculate(state, node_key)?
+            } else {
+                // skip calculation, redo at correct height
+                false
+            };
+
+            if !calculation_complete {
+                state.queue_recalc(node_key);
+            }
+        }
+        Ok(())
+    }
+
+    fn stabilize0_parallel(
+        &self,
+        state: &mut GraphState,
+        workers: &WorkerPool,
+    ) -> Result<(), Error> {
+        while let Some((height, batch)) = state.recalc_pop_all_next() {
+            let mut polling = Vec::with_capacity(batch.len());
+            for node_key in batch {
+                let node = state.node_mut(node_key).unwrap();
+                if node.height == height {
+                    polling.push((node_key, node.anchor.take().unwrap()));
+                } else {
+                    // skip calculation, redo at correct height
+                    state.queue_recalc(node_key);
+                }
+            }
+
+            let polled = self.poll_all(state, workers, &mut polling);
+
+            let node_keys: Vec<NodeKey> = polling
+                .into_iter()
+                .map(|(node_key, anchor)| {
+                    state.node_mut(node_key).unwrap().anchor = Some(anchor);
+                    node_key
+                })
+                .collect();
+            self.graph.drain_inbox(state);
+            // the whole batch was polled already, so finish all of it before reporting errors
+            let mut res = Ok(());
+            for (node_key, polled) in node_keys.into_iter().zip(polled) {
+                match self.finish_recalculation(state, node_key, polled) {
+                    Ok(true) => {}
+                    Ok(false) => state.queue_recalc(node_key),
+                    Err(err) => res = res.and(Err(err)),
+                }
+            }
+            res?;
+        }
+        Ok(())
+    }
+
+    /// Polls each of `polling`'s anchors, spreading them across the engine's workers.
+    fn poll_all(
+        &self,
+        state: &GraphState,
+        workers: &WorkerPool,
+        polling: &mut [(NodeKey, Box<dyn GenericAnchor>)],
+    ) -> Vec<Polled> {
+        if polling.len() < MIN_PARALLEL_BATCH {
+            return polling
+                .iter_mut()
+                .map(|(node_key, anchor)| self.poll(state, *node_key, anchor))
+                .collect();
+        }
+        let chunk_size = (polling.len() + workers.len() - 1) / workers.len();
+        let chunks = polling.chunks_mut(chunk_size).map(|chunk| {
+            move || {
+                let _mounter = self.enter_guard();
+                chunk
+                    .iter_mut()
+                    .map(|(node_key, anchor)| self.poll(state, *node_key, anchor))
+                    .collect::<Vec<_>>()
+            }
+        });
+        workers.run_all(chunks).into_iter().flatten().collect()
+    }
+
+    fn poll(
+        &self,
+        state: &GraphState,
+        node_key: NodeKey,
+        anchor: &mut Box<dyn GenericAnchor>,
+    ) -> Polled {
+        let mut ecx = EngineContextMut::new(&self.graph, state, node_key);
+        let poll = anchor.poll_updated(&mut ecx);
+        ecx.finish(poll)
+    }
+
+    /// returns false if calculation is still pending
+    fn recalculate(&self, state: &mut GraphState, node_key: NodeKey) -> Result<bool, Error> {
+        let mut anchor = state.node_mut(node_key).unwrap().anchor.take().unwrap();
+        let polled = self.poll(state, node_key, &mut anchor);
+        state.node_mut(node_key).unwrap().anchor = Some(anchor);
+
+        // bring in anchors mounted while polling, since the ops may refer to them
+        self.graph.drain_inbox(state);
+        self.finish_recalculation(state, node_key, polled)
+    }
+
+    /// Applies the outcome of polling a node to the graph.
+    ///
+    /// returns false if calculation is still pending
+    fn finish_recalculation(
+        &self,
+        state: &mut GraphState,
+        node_key: NodeKey,
+        polled: Polled,
+    ) -> Result<bool, Error> {
+        if state.node(node_key).is_none() {
+            // the last handle to this node was dropped while polling it
+            return Ok(true);
+        }
+        let res = polled
+            .ops
+            .into_iter()
+            .try_for_each(|op| op.apply(state, node_key));
+        if let Err(err) = polled.error.map_or(res, Err) {
+            // the node bailed, so it needs to be polled again once the problem is fixed
+            state.node_mut(node_key).unwrap().recalc_state = RecalcState::Needed;
+            return Err(err);
+        }
+
+        let calculation_complete = match polled.poll {
+            Poll::Pending => {
+                if polled.pending_on_anchor_get {
+                    // looks like we requested an anchor that isn't yet calculated, so we
+                    // reinsert into the graph directly; our height either was higher than this
+                    // requested anchor's already, or it was updated so it's higher now.
+                    false
+                } else {
+                    // we're waiting for some non-anchors future, or for an anchor that is
+                    // waiting itself. polling again is pointless until we're woken up.
+                    state.node_mut(node_key).unwrap().recalc_state = RecalcState::Waiting;
+                    true
+                }
+            }
+            Poll::Updated => {
+                // make sure all parents are marked as dirty, and observed parents are recalculated
+                state.mark_dirty(node_key, true);
+                let node = state.node_mut(node_key).unwrap();
+                node.last_update = Some(self.generation);
+                node.last_ready = Some(self.generation);
+                true
+            }
+            Poll::Unchanged => {
+                state.node_mut(node_key).unwrap().last_ready = Some(self.generation);
+                true
+            }
+        };
+        Ok(calculation_complete)
+    }
+
+    /// Returns whether an Anchor is Observed, Necessary, or Unnecessary.
+    pub fn check_observed<T>(&self, anchor: &Anchor<T>) -> ObservedState {
+        self.try_check_observed(anchor)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Returns whether an Anchor is observed like `Engine::check_observed`, but reports misuse
+    /// as an error instead of panicking.
+    pub fn try_check_observed<T>(&self, anchor: &Anchor<T>) -> Result<ObservedState, Error> {
+        let state = self.graph.lock()?;
+        let node_key = anchor.key().node_key;
+        state
+            .node(node_key)
+            .ok_or(Error::ForeignAnchor { requested_by: None })?;
+        Ok(state.check_observed(node_key))
+    }
+
+    /// Returns the generation of the latest stabilization.
+    pub fn current_generation(&self) -> Generation {
+        self.generation
+    }
+
+    /// Returns the generation of the latest stabilization that changed `anchor`'s value, unless
+    /// it was never calculated.
+    ///
+    /// This doesn't bring `anchor` up-to-date.
+    pub fn last_changed<T>(&self, anchor: &Anchor<T>) -> Option<Generation> {
+        self.try_last_changed(anchor)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Returns when `anchor` last changed like `Engine::last_changed`, but reports misuse as an
+    /// error instead of panicking.
+    pub fn try_last_changed<T>(&self, anchor: &Anchor<T>) -> Result<Option<Generation>, Error> {
+        Ok(self
+            .graph
+            .lock()?
+            .node(anchor.key().node_key)
+            .ok_or(Error::ForeignAnchor { requested_by: None })?
+            .last_update)
+    }
+
+    /// Returns whether a stabilization after `generation` changed `anchor`'s value.
+    ///
+    /// This doesn't bring `anchor` up-to-date. Use `Engine::try_last_changed` to report misuse
+    /// as an error instead of panicking.
+    pub fn changed_since<T>(&self, anchor: &Anchor<T>, generation: Generation) -> bool {
+        self.last_changed(anchor)
+            .map_or(false, |last_changed| last_changed > generation)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Engine {
+    /// Registers `variable` under `key`, so its value is included in this engine's snapshots.
+    ///
+    /// Panics if another variable is already registered under `key`.
+    pub fn register_variable<T>(&mut self, key: impl Into<String>, variable: &super::Variable<T>)
+    where
+        T: 'static + Send + Sync + serde::Serialize + serde::de::DeserializeOwned,
+    {
+        self.snapshots
+            .register(key.into(), Box::new(variable.clone()));
+    }
+
+    /// Removes the variable registered under `key` from this engine's snapshots.
+    ///
+    /// Returns whether a variable was registered under it.
+    pub fn unregister_variable(&mut self, key: &str) -> bool {
+        self.snapshots.unregister(key)
+    }
+
+    /// Captures the current values of all registered variables.
+    pub fn snapshot(&self) -> Result<crate::core::Snapshot, crate::core::SnapshotError> {
+        self.snapshots.snapshot()
+    }
+
+    /// Sets all variables in `snapshot` to their captured values at once, like a
+    /// `Transaction` would.
+    ///
+    /// Variables whose values are unchanged aren't set at all, so only anchors depending on
+    /// changed values are recalculated. If any value can't be restored, no variable is set.
+    pub fn restore(
+        &mut self,
+        snapshot: &crate::core::Snapshot,
+    ) -> Result<(), crate::core::SnapshotError> {
+        self.snapshots.stage_restore(snapshot)?.commit();
+        Ok(())
+    }
+}
+
+/// Mounts new anchors created on the current thread onto an engine, until dropped.
+///
+/// Returned by `Engine::enter_guard`. Dropping it makes the previously current engine current
+/// again.
+pub struct EnterGuard {
+    previous: Option<Arc<Graph>>,
+    // restores a thread-local, so it must be dropped on the thread that created it
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        DEFAULT_MOUNTER.with(|v| *v.borrow_mut() = previous);
+    }
+}
@@ -0,0 +1,500 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    task::{self, Context, Waker},
+};
+
+use crate::{
+    core::{AnchorDebugInfo, ObservedState},
+    Error,
+};
+
+use super::{AnchorHandle, AnchorKey, Generation, GenericAnchor, NodeKey};
+
+#[derive(Copy, Clone, Default, Eq, PartialEq, Hash, Debug)]
+pub(super) enum RecalcState {
+    #[default]
+    Needed,
+    Pending,
+    Ready,
+    /// polled, but waiting for some future to wake it up
+    Waiting,
+}
+
+static NEXT_TOKEN: AtomicU32 = AtomicU32::new(0);
+
+pub(super) struct Graph {
+    token: u32,
+    state: Mutex<GraphState>,
+    inbox: Mutex<Inbox>,
+    // notified whenever a dirty mark is pushed to the inbox
+    dirtied: Condvar,
+}
+
+/// Changes made without holding the graph's lock, applied the next time the engine locks it.
+///
+/// Handles, dirty handles and mounts only ever touch the inbox, so they may be used from
+/// any thread, and even while the engine is busy polling some `AnchorCore`.
+#[derive(Default)]
+pub(super) struct Inbox {
+    pub(super) mounted: Vec<(NodeKey, Box<dyn GenericAnchor>)>,
+    pub(super) dropped: Vec<NodeKey>,
+    pub(super) dirty: Vec<NodeKey>,
+
+    // wakers of tasks awaiting `Graph::dirtied`
+    tasks: Vec<Waker>,
+
+    /// freed slots, along with the generation their next node will have
+    free: Vec<(usize, u32)>,
+    next_index: usize,
+}
+
+impl Graph {
+    pub(super) fn new() -> Self {
+        let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+        Self {
+            token,
+            state: Mutex::new(GraphState::new(token)),
+            inbox: Mutex::new(Inbox::default()),
+            dirtied: Condvar::new(),
+        }
+    }
+
+    pub(super) fn accepts_key(&self, node_key: NodeKey) -> bool {
+        node_key.token == self.token
+    }
+
+    pub(super) fn inbox(&self) -> MutexGuard<'_, Inbox> {
+        // the inbox is only ever pushed to, so it's still consistent after a panic
+        self.inbox.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the graph, bringing in any nodes mounted or dropped since it was last locked.
+    pub(super) fn lock(&self) -> Result<MutexGuard<'_, GraphState>, Error> {
+        let mut state = self.state.lock().map_err(|_| Error::Poisoned)?;
+        self.drain_inbox(&mut state);
+        Ok(state)
+    }
+
+    pub(super) fn insert(self: &Arc<Self>, anchor: Box<dyn GenericAnchor>) -> AnchorHandle {
+        let mut inbox = self.inbox();
+        let (index, generation) = inbox.free.pop().unwrap_or_else(|| {
+            let index = inbox.next_index;
+            inbox.next_index += 1;
+            (index, 0)
+        });
+        let node_key = NodeKey {
+            index,
+            generation,
+            token: self.token,
+        };
+        inbox.mounted.push((node_key, anchor));
+        AnchorHandle::new(node_key, Arc::downgrade(self))
+    }
+
+    /// Moves newly mounted nodes into the graph, and frees nodes with no remaining handles.
+    ///
+    /// Must not be called while any node's anchor is taken out for polling.
+    pub(super) fn drain_inbox(&self, state: &mut GraphState) {
+        loop {
+            let (mounted, dropped) = {
+                let mut inbox = self.inbox();
+                (
+                    std::mem::take(&mut inbox.mounted),
+                    std::mem::take(&mut inbox.dropped),
+                )
+            };
+            if mounted.is_empty() && dropped.is_empty() {
+                return;
+            }
+            for (node_key, anchor) in mounted {
+                state.insert(node_key, anchor);
+            }
+            for node_key in dropped {
+                if let Some(node) = state.remove(node_key) {
+                    self.inbox()
+                        .free
+                        .push((node_key.index, node_key.generation.wrapping_add(1)));
+                    // "SAFETY": this may drop other handles, which are pushed to the inbox
+                    std::mem::drop(node);
+                }
+            }
+        }
+    }
+
+    pub(super) fn take_dirty_marks(&self) -> Vec<NodeKey> {
+        std::mem::take(&mut self.inbox().dirty)
+    }
+
+    pub(super) fn push_dirty_mark(&self, node_key: NodeKey) {
+        let tasks = {
+            let mut inbox = self.inbox();
+            inbox.dirty.push(node_key);
+            std::mem::take(&mut inbox.tasks)
+        };
+        self.dirtied.notify_all();
+        for task in tasks {
+            task.wake();
+        }
+    }
+
+    /// Blocks until some dirty mark is waiting in the inbox.
+    pub(super) fn wait_for_dirty_marks(&self) {
+        let _inbox = self
+            .dirtied
+            .wait_while(self.inbox(), |inbox| inbox.dirty.is_empty())
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+
+    /// Resolves once some dirty mark is waiting in the inbox.
+    pub(super) fn dirtied(&self) -> Dirtied<'_> {
+        Dirtied { graph: self }
+    }
+}
+
+/// Future returned by `Graph::dirtied`.
+pub(super) struct Dirtied<'a> {
+    graph: &'a Graph,
+}
+
+impl Future for Dirtied<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> task::Poll<()> {
+        let mut inbox = self.graph.inbox();
+        if inbox.dirty.is_empty() {
+            inbox.tasks.push(cx.waker().clone());
+            task::Poll::Pending
+        } else {
+            task::Poll::Ready(())
+        }
+    }
+}
+
+pub(super) struct GraphState {
+    token: u32,
+    nodes: Vec<Option<Node>>,
+
+    /// height -> nodes queued for recalculation at that height
+    recalc_queues: Vec<Vec<NodeKey>>,
+    recalc_min_height: usize,
+}
+
+pub(super) struct Node {
+    generation: u32,
+
+    pub(super) observed: bool,
+
+    /// Bool used during height incrementing to check for loops
+    visited: bool,
+
+    /// Number of nodes that list `self` as a necessary child.
+    pub(super) necessary_count: usize,
+
+    pub(super) debug_info: AnchorDebugInfo,
+
+    /// Tracks when this `Node` was last polled as `Updated` or `Unchanged`.
+    pub(super) last_ready: Option<Generation>,
+    /// Tracks when this `Node` was last polled as `Updated`.
+    pub(super) last_update: Option<Generation>,
+
+    /// `None` while the anchor is taken out of the graph to be polled
+    pub(super) anchor: Option<Box<dyn GenericAnchor>>,
+
+    /// unsorted, duplicates may exist
+    clean_parents: Vec<NodeKey>,
+
+    /// sorted in key order
+    necessary_children: Vec<NodeKey>,
+
+    pub(super) height: usize,
+    pub(super) recalc_state: RecalcState,
+}
+
+impl GraphState {
+    fn new(token: u32) -> Self {
+        Self {
+            token,
+            nodes: vec![],
+            recalc_queues: vec![],
+            recalc_min_height: 0,
+        }
+    }
+
+    pub(super) fn accepts_key(&self, node_key: NodeKey) -> bool {
+        node_key.token == self.token
+    }
+
+    /// Returns the node for `node_key`, or `None` if it has since been freed.
+    pub(super) fn node(&self, node_key: NodeKey) -> Option<&Node> {
+        if !self.accepts_key(node_key) {
+            return None;
+        }
+        self.nodes
+            .get(node_key.index)?
+            .as_ref()
+            .filter(|node| node.generation == node_key.generation)
+    }
+
+    pub(super) fn node_mut(&mut self, node_key: NodeKey) -> Option<&mut Node> {
+        if !self.accepts_key(node_key) {
+            return None;
+        }
+        self.nodes
+            .get_mut(node_key.index)?
+            .as_mut()
+            .filter(|node| node.generation == node_key.generation)
+    }
+
+    fn insert(&mut self, node_key: NodeKey, anchor: Box<dyn GenericAnchor>) {
+        if self.nodes.len() <= node_key.index {
+            self.nodes.resize_with(node_key.index + 1, || None);
+        }
+        self.nodes[node_key.index] = Some(Node {
+            generation: node_key.generation,
+            observed: false,
+            visited: false,
+            necessary_count: 0,
+            debug_info: anchor.debug_info(),
+            last_ready: None,
+            last_update: None,
+            anchor: Some(anchor),
+            clean_parents: vec![],
+            necessary_children: vec![],
+            height: 0,
+            recalc_state: RecalcState::Needed,
+        });
+    }
+
+    fn remove(&mut self, node_key: NodeKey) -> Option<Node> {
+        self.node(node_key)?;
+        let node = self.nodes[node_key.index].take()?;
+        for child in &node.necessary_children {
+            if let Some(child) = self.node_mut(*child) {
+                child.necessary_count -= 1;
+            }
+        }
+        // stale keys left in the recalc queues are skipped once popped
+        Some(node)
+    }
+
+    pub(super) fn recalc_pop_next(&mut self) -> Option<(usize, NodeKey)> {
+        while self.recalc_min_height < self.recalc_queues.len() {
+            let height = self.recalc_min_height;
+            match self.recalc_queues[height].pop() {
+                Some(node_key) => {
+                    if let Some(node) = self.node_mut(node_key) {
+                        if node.recalc_state == RecalcState::Pending {
+                            node.recalc_state = RecalcState::Ready;
+                            return Some((height, node_key));
+                        }
+                    }
+                }
+                None => self.recalc_min_height += 1,
+            }
+        }
+        None
+    }
+
+    /// Pops every node queued at the lowest queued height, in the order `recalc_pop_next`
+    /// would have returned them.
+    ///
+    /// Nodes sharing a height never depend on each other, so they can be recalculated in any order.
+    pub(super) fn recalc_pop_all_next(&mut self) -> Option<(usize, Vec<NodeKey>)> {
+        while self.recalc_min_height < self.recalc_queues.len() {
+            let height = self.recalc_min_height;
+            let queue = std::mem::take(&mut self.recalc_queues[height]);
+            let batch: Vec<NodeKey> = queue
+                .into_iter()
+                .rev()
+                .filter(|node_key| match self.node_mut(*node_key) {
+                    Some(node) if node.recalc_state == RecalcState::Pending => {
+                        node.recalc_state = RecalcState::Ready;
+                        true
+                    }
+                    _ => false,
+                })
+                .collect();
+            if !batch.is_empty() {
+                return Some((height, batch));
+            }
+            self.recalc_min_height += 1;
+        }
+        None
+    }
+
+    pub(super) fn queue_recalc(&mut self, node_key: NodeKey) {
+        let node = match self.node_mut(node_key) {
+            Some(node) => node,
+            None => return,
+        };
+        if node.recalc_state == RecalcState::Pending {
+            // already in recalc queue
+            return;
+        }
+        node.recalc_state = RecalcState::Pending;
+        let node_height = node.height;
+        if self.recalc_queues.len() <= node_height {
+            self.recalc_queues.resize_with(node_height + 1, Vec::new);
+        }
+        self.recalc_queues[node_height].push(node_key);
+        self.recalc_min_height = self.recalc_min_height.min(node_height);
+    }
+
+    /// Returns whether an Anchor is Observed, Necessary, or Unnecessary.
+    pub(super) fn check_observed(&self, node_key: NodeKey) -> ObservedState {
+        let node = self.node(node_key).unwrap();
+        if node.observed {
+            ObservedState::Observed
+        } else if node.necessary_count > 0 {
+            ObservedState::Necessary
+        } else {
+            ObservedState::Unnecessary
+        }
+    }
+
+    pub(super) fn add_clean_parent(&mut self, child: NodeKey, parent: NodeKey) {
+        if let Some(child) = self.node_mut(child) {
+            child.clean_parents.push(parent);
+        }
+    }
+
+    fn drain_clean_parents(&mut self, node_key: NodeKey) -> Vec<NodeKey> {
+        self.node_mut(node_key)
+            .map(|node| std::mem::take(&mut node.clean_parents))
+            .unwrap_or_default()
+    }
+
+    pub(super) fn add_necessary_child(&mut self, parent: NodeKey, child: NodeKey) {
+        let necessary_children = match self.node_mut(parent) {
+            Some(parent) => &mut parent.necessary_children,
+            None => return,
+        };
+        if let Err(i) = necessary_children.binary_search(&child) {
+            necessary_children.insert(i, child);
+            if let Some(child) = self.node_mut(child) {
+                child.necessary_count += 1;
+            }
+        }
+    }
+
+    pub(super) fn remove_necessary_child(&mut self, parent: NodeKey, child: NodeKey) {
+        let necessary_children = match self.node_mut(parent) {
+            Some(parent) => &mut parent.necessary_children,
+            None => return,
+        };
+        if let Ok(i) = necessary_children.binary_search(&child) {
+            necessary_children.remove(i);
+            if let Some(child) = self.node_mut(child) {
+                child.necessary_count -= 1;
+            }
+        }
+    }
+
+    pub(super) fn update_necessary_children(&mut self, node_key: NodeKey) {
+        if self.node(node_key).is_none()
+            || self.check_observed(node_key) != ObservedState::Unnecessary
+        {
+            // we have another parent still observed, so skip this
+            return;
+        }
+        let children = std::mem::take(&mut self.node_mut(node_key).unwrap().necessary_children);
+        for child in children {
+            if let Some(child_node) = self.node_mut(child) {
+                child_node.necessary_count -= 1;
+            }
+            self.update_necessary_children(child);
+        }
+    }
+
+    // skip_self = true indicates output has *definitely* changed, but node has been recalculated
+    // skip_self = false indicates node has not yet been recalculated
+    pub(super) fn mark_dirty(&mut self, node_key: NodeKey, skip_self: bool) {
+        if skip_self {
+            for parent in self.drain_clean_parents(node_key) {
+                self.mark_parent_dirty(parent, node_key);
+            }
+        } else {
+            self.mark_dirty0(node_key);
+        }
+    }
+
+    fn mark_dirty0(&mut self, node_key: NodeKey) {
+        let node = match self.node_mut(node_key) {
+            Some(node) => node,
+            None => return,
+        };
+        if node.observed || node.necessary_count > 0 {
+            self.queue_recalc(node_key);
+        } else if matches!(node.recalc_state, RecalcState::Ready | RecalcState::Waiting) {
+            node.recalc_state = RecalcState::Needed;
+            for parent in self.drain_clean_parents(node_key) {
+                self.mark_parent_dirty(parent, node_key);
+            }
+        }
+    }
+
+    fn mark_parent_dirty(&mut self, parent: NodeKey, child: NodeKey) {
+        let anchor = match self.node_mut(parent).and_then(|node| node.anchor.as_mut()) {
+            Some(anchor) => anchor,
+            None => return,
+        };
+        anchor.mark_dirty(AnchorKey::new(child));
+        self.mark_dirty0(parent);
+    }
+
+    /// Returns `Ok(true)` if `child` was already lower than `parent`, and `Ok(false)` if
+    /// `parent`'s height had to be increased.
+    pub(super) fn ensure_height_increases(
+        &mut self,
+        child: NodeKey,
+        parent: NodeKey,
+    ) -> Result<bool, Error> {
+        let child_height = self.node(child).map_or(0, |child| child.height);
+        if child_height < self.node(parent).unwrap().height {
+            return Ok(true);
+        }
+        if let Some(child) = self.node_mut(child) {
+            child.visited = true;
+        }
+        let res = self.set_min_height(parent, child_height + 1);
+        if let Some(child) = self.node_mut(child) {
+            child.visited = false;
+        }
+        res.map(|()| false)
+    }
+
+    fn set_min_height(&mut self, node_key: NodeKey, min_height: usize) -> Result<(), Error> {
+        let node = match self.node_mut(node_key) {
+            Some(node) => node,
+            None => return Ok(()),
+        };
+        if node.visited {
+            return Err(Error::Cycle {
+                path: vec![node.debug_info.to_string()],
+            });
+        }
+
+        node.visited = true;
+
+        let mut res = Ok(());
+        if node.height < min_height {
+            node.height = min_height;
+            for parent in node.clean_parents.clone() {
+                if let Err(err) = self.set_min_height(parent, min_height + 1) {
+                    // report the first error, but keep raising the remaining parents
+                    let node = self.node(node_key).unwrap();
+                    res = res.and(Err(err.via(|| node.debug_info.to_string())));
+                }
+            }
+        }
+
+        self.node_mut(node_key).unwrap().visited = false;
+
+        res
+    }
+}
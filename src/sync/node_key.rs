@@ -0,0 +1,10 @@
+/// Identifies a node slot within a thread-safe graph.
+///
+/// Slots are reused once their node has been freed, so `generation` is bumped on every reuse
+/// to make stale keys detectable.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub(super) struct NodeKey {
+    pub(super) index: usize,
+    pub(super) generation: u32,
+    pub(super) token: u32,
+}
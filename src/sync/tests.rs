@@ -0,0 +1,180 @@
+use std::sync::{Arc, Mutex};
+
+use super::{Anchor, Engine, Variable};
+
+mod shared {
+    include!("../test_util/engine_tests.rs");
+}
+
+#[test]
+fn test_anchors_are_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<Anchor<usize>>();
+    assert_send_sync::<Variable<String>>();
+    assert_send_sync::<Engine>();
+}
+
+#[test]
+fn test_then_mounting_new_anchors() {
+    let mut engine = Engine::new();
+    let v = Variable::new(1usize);
+    let a = v.watch().then(|num| {
+        let num = *num;
+        Anchor::constant(num).map(move |n| n * 10)
+    });
+    assert_eq!(engine.get(&a), 10);
+
+    v.set(2);
+    assert_eq!(engine.get(&a), 20);
+}
+
+#[test]
+fn test_set_from_other_thread() {
+    let mut engine = Engine::new();
+    let v = Variable::new(1usize);
+    let doubled = v.watch().map(|n| n * 2);
+    engine.mark_observed(&doubled);
+    assert_eq!(engine.get(&doubled), 2);
+
+    let setter = v.clone();
+    std::thread::spawn(move || setter.set(21)).join().unwrap();
+    assert_eq!(engine.get(&doubled), 42);
+
+    // anchors may be read on other threads, too
+    let handle = std::thread::spawn(move || {
+        engine.mark_unobserved(&doubled);
+        engine.get(&doubled)
+    });
+    assert_eq!(handle.join().unwrap(), 42);
+}
+
+fn build_wide_graph(width: usize) -> (Variable<usize>, Anchor<Vec<usize>>) {
+    let input = Variable::new(1usize);
+    let level0: Vec<Anchor<usize>> = (0..width)
+        .map(|i| input.watch().map(move |n| n * i))
+        .collect();
+    let level1: Vec<Anchor<usize>> = level0
+        .iter()
+        .enumerate()
+        .map(|(i, anchor)| {
+            anchor.then(move |n| {
+                let n = *n;
+                if i % 2 == 0 {
+                    Anchor::constant(n).map(|n| n + 1)
+                } else {
+                    Anchor::constant(n)
+                }
+            })
+        })
+        .collect();
+    (input, level1.into_iter().collect())
+}
+
+#[test]
+fn test_parallel_matches_sequential() {
+    let mut sequential = Engine::new();
+    let (sequential_input, sequential_output) = build_wide_graph(64);
+    let mut parallel = Engine::new_with_workers(4);
+    let (parallel_input, parallel_output) = build_wide_graph(64);
+    parallel.mark_observed(&parallel_output);
+
+    assert_eq!(
+        sequential.get(&sequential_output),
+        parallel.get(&parallel_output)
+    );
+
+    for n in 2..5 {
+        sequential_input.set(n);
+        parallel_input.set(n);
+        assert_eq!(
+            sequential.get(&sequential_output),
+            parallel.get(&parallel_output)
+        );
+    }
+}
+
+#[test]
+fn test_parallel_uses_workers() {
+    use std::{
+        collections::HashSet,
+        thread::{self, ThreadId},
+    };
+
+    let mut engine = Engine::new_with_workers(2);
+    let input = Variable::new(1usize);
+    let threads: Arc<Mutex<HashSet<ThreadId>>> = Default::default();
+    let nodes: Vec<Anchor<usize>> = (0..8)
+        .map(|_| {
+            let threads = Arc::clone(&threads);
+            input.watch().map(move |n| {
+                threads.lock().unwrap().insert(thread::current().id());
+                *n
+            })
+        })
+        .collect();
+    let sum: Anchor<usize> = nodes
+        .into_iter()
+        .collect::<Anchor<Vec<usize>>>()
+        .map(|nums| nums.iter().sum());
+    assert_eq!(engine.get(&sum), 8);
+
+    // the same threads are used for every stabilization
+    input.set(2);
+    assert_eq!(engine.get(&sum), 16);
+
+    let threads = threads.lock().unwrap();
+    assert_eq!(threads.len(), 2);
+    assert!(!threads.contains(&thread::current().id()));
+}
+
+#[test]
+fn test_parallel_polls_small_batches_inline() {
+    use std::thread::{self, ThreadId};
+
+    let mut engine = Engine::new_with_workers(2);
+    let input = Variable::new(1usize);
+    let threads: Arc<Mutex<Vec<ThreadId>>> = Default::default();
+    let doubled = {
+        let threads = Arc::clone(&threads);
+        input.watch().map(move |n| {
+            threads.lock().unwrap().push(thread::current().id());
+            n * 2
+        })
+    };
+    assert_eq!(engine.get(&doubled), 2);
+    assert_eq!(*threads.lock().unwrap(), vec![thread::current().id()]);
+}
+
+#[test]
+fn test_from_future_blocking_get_with_workers() {
+    shared::check_from_future_blocking_get(Engine::new_with_workers(2));
+}
+
+#[test]
+fn test_clock_advanced_from_other_thread() {
+    use std::time::Duration;
+
+    let mut engine = Engine::new();
+    let clock = engine.clock();
+    let start = clock.current();
+    let second = Duration::from_secs(1);
+
+    let input = Variable::new(1);
+    let debounced = input.watch().debounce(&clock, second);
+    let ticks = clock.every(second);
+    assert_eq!(engine.get(&debounced), 1);
+
+    // the clock may be advanced from any thread
+    let remote_clock = clock.clone();
+    std::thread::spawn(move || {
+        input.set(2);
+        remote_clock.advance_to(start + second / 2);
+    })
+    .join()
+    .unwrap();
+    assert_eq!(engine.get(&debounced), 1);
+    clock.advance_to(start + 2 * second);
+    assert_eq!(engine.get(&debounced), 2);
+    assert_eq!(engine.get(&ticks), 2);
+}
@@ -0,0 +1,6 @@
+use super::Engine;
+
+/// A variable that exposes an anchor for its value.
+///
+/// Variables may be sent to and set from any thread.
+pub type Variable<T> = crate::core::Variable<T, Engine>;
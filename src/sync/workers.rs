@@ -0,0 +1,113 @@
+use std::{
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed set of threads, which the engine hands batches of polls to.
+///
+/// The threads live as long as the pool, so stabilizing doesn't spawn any.
+pub(super) struct WorkerPool {
+    // `mpsc::Sender` isn't `Sync` before Rust 1.72
+    jobs: Mutex<Option<mpsc::Sender<Job>>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    pub(super) fn new(workers: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let threads = (0..workers)
+            .map(|i| {
+                let receiver = Arc::clone(&receiver);
+                thread::Builder::new()
+                    .name(format!("anchors-worker-{}", i))
+                    .spawn(move || loop {
+                        let job = receiver
+                            .lock()
+                            .unwrap_or_else(|err| err.into_inner())
+                            .recv();
+                        match job {
+                            Ok(job) => job(),
+                            // the pool was dropped
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("failed to spawn worker thread")
+            })
+            .collect();
+        Self {
+            jobs: Mutex::new(Some(sender)),
+            threads,
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Runs all `tasks` on the pool's threads, returning their results in order.
+    ///
+    /// Blocks until every task finished. If any of them panicked, the first panic is resumed
+    /// afterwards.
+    pub(super) fn run_all<'a, T, F>(&self, tasks: impl IntoIterator<Item = F>) -> Vec<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'a,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let mut count = 0;
+        {
+            let jobs = self.jobs.lock().unwrap_or_else(|err| err.into_inner());
+            let jobs = jobs.as_ref().unwrap();
+            for (i, task) in tasks.into_iter().enumerate() {
+                let sender = sender.clone();
+                let job: Box<dyn FnOnce() + Send + 'a> = Box::new(move || {
+                    let result = panic::catch_unwind(AssertUnwindSafe(task));
+                    // the task was consumed, so nothing borrowed outlives this send
+                    let _ = sender.send((i, result));
+                });
+                // SAFETY: we wait below until every job sent its result, and jobs don't touch
+                // anything borrowed for `'a` after sending it.
+                let job: Job = unsafe { mem::transmute(job) };
+                if let Err(mpsc::SendError(job)) = jobs.send(job) {
+                    // the threads never exit while the pool is alive, but don't leave the
+                    // task undone if they did
+                    job();
+                }
+                count += 1;
+            }
+        }
+        drop(sender);
+
+        let mut results: Vec<Option<thread::Result<T>>> = (0..count).map(|_| None).collect();
+        for _ in 0..count {
+            let (i, result) = receiver.recv().expect("worker thread exited");
+            results[i] = Some(result);
+        }
+        results
+            .into_iter()
+            .map(|result| {
+                result
+                    .unwrap()
+                    .unwrap_or_else(|err| panic::resume_unwind(err))
+            })
+            .collect()
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // closing the channel makes the threads exit
+        self.jobs
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
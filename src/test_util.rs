@@ -0,0 +1,63 @@
+//! Fixtures shared by the engines' tests.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{self, Context, Wake, Waker},
+    thread::{self, Thread},
+};
+
+/// A minimal oneshot channel, whose receiving end is a future.
+pub(crate) fn oneshot<T>() -> (impl Fn(T), Receiver<T>) {
+    let shared: Arc<Mutex<(Option<T>, Option<Waker>)>> = Default::default();
+    let sender = {
+        let shared = Arc::clone(&shared);
+        move |value| {
+            let mut shared = shared.lock().unwrap();
+            shared.0 = Some(value);
+            if let Some(waker) = shared.1.take() {
+                waker.wake();
+            }
+        }
+    };
+    (sender, Receiver(shared))
+}
+
+pub(crate) struct Receiver<T>(Arc<Mutex<(Option<T>, Option<Waker>)>>);
+
+impl<T> Future for Receiver<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> task::Poll<T> {
+        let mut shared = self.0.lock().unwrap();
+        match shared.0.take() {
+            Some(value) => task::Poll::Ready(value),
+            None => {
+                shared.1 = Some(cx.waker().clone());
+                task::Poll::Pending
+            }
+        }
+    }
+}
+
+/// Runs a future to completion on the current thread.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            task::Poll::Ready(output) => return output,
+            task::Poll::Pending => thread::park(),
+        }
+    }
+}
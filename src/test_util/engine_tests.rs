@@ -0,0 +1,528 @@
+// Tests shared by both engines, included by each engine's `tests` module as its `shared`
+// module, where `Engine`, `Variable` and `Anchor` are that engine's types.
+//
+// They can't be generic over `Runtime` instead: mounting their closures needs `Mount` bounds
+// naming the closures' types, and the `single_threaded` anchors they capture aren't `Send`.
+// Including them rather than expanding a macro keeps the anchors' debug locations distinct.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use crate::{
+    test_util::{block_on, oneshot},
+    Error, MultiAnchor,
+};
+
+use super::super::{Anchor, BeforeOrAfter, Engine, ObservedState, Variable};
+
+#[test]
+fn test_map_simple() {
+    let mut engine = Engine::new();
+    let v1 = Variable::new(1usize);
+    let v2 = Variable::new(123usize);
+    let _a2 = v1.watch().map(|num1| *num1);
+    let a = MultiAnchor::map((&v1.watch(), &v2.watch()), |num1, num2| num1 + num2);
+    let b = MultiAnchor::map((&v1.watch(), &a, &v2.watch()), |num1, num2, num3| {
+        num1 + num2 + num3
+    });
+    engine.mark_observed(&b);
+    engine.stabilize();
+    assert_eq!(engine.get(&b), 248);
+
+    v1.set(2);
+    assert_eq!(engine.get(&b), 250);
+}
+
+#[test]
+fn test_then_simple() {
+    let mut engine = Engine::new();
+    let v1 = Variable::new(true);
+    let v2 = Variable::new(10usize).watch();
+    let v3 = Variable::new(20usize).watch();
+    let a = v1
+        .watch()
+        .then(move |val| if *val { v2.clone() } else { v3.clone() });
+    engine.mark_observed(&a);
+    engine.stabilize();
+    assert_eq!(engine.get(&a), 10);
+
+    v1.set(false);
+    engine.stabilize();
+    assert_eq!(engine.get(&a), 20);
+}
+
+#[test]
+fn test_cutoff_simple_observed() {
+    let mut engine = Engine::new();
+    let v = Variable::new(100i32);
+    let mut old_val = 0i32;
+    let post_cutoff = v
+        .watch()
+        .cutoff(move |new_val| {
+            if (old_val - *new_val).abs() < 50 {
+                false
+            } else {
+                old_val = *new_val;
+                true
+            }
+        })
+        .map(|v| *v + 10);
+    engine.mark_observed(&post_cutoff);
+    assert_eq!(engine.get(&post_cutoff), 110);
+    v.set(125);
+    assert_eq!(engine.get(&post_cutoff), 110);
+    v.set(151);
+    assert_eq!(engine.get(&post_cutoff), 161);
+    v.set(125);
+    assert_eq!(engine.get(&post_cutoff), 161);
+}
+
+#[test]
+fn test_observed_marking() {
+    let mut engine = Engine::new();
+    let v1 = Variable::new(1usize).watch();
+    let a = v1.map(|num1| *num1 + 1);
+    let b = a.map(|num1| *num1 + 2);
+    let c = b.map(|num1| *num1 + 3);
+    engine.mark_observed(&a);
+    engine.mark_observed(&c);
+
+    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&v1));
+    assert_eq!(ObservedState::Observed, engine.check_observed(&a));
+    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&b));
+    assert_eq!(ObservedState::Observed, engine.check_observed(&c));
+
+    engine.stabilize();
+
+    assert_eq!(ObservedState::Necessary, engine.check_observed(&v1));
+    assert_eq!(ObservedState::Observed, engine.check_observed(&a));
+    assert_eq!(ObservedState::Necessary, engine.check_observed(&b));
+    assert_eq!(ObservedState::Observed, engine.check_observed(&c));
+
+    engine.mark_unobserved(&c);
+
+    assert_eq!(ObservedState::Necessary, engine.check_observed(&v1));
+    assert_eq!(ObservedState::Observed, engine.check_observed(&a));
+    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&b));
+    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&c));
+
+    engine.mark_unobserved(&a);
+
+    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&v1));
+    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&a));
+    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&b));
+    assert_eq!(ObservedState::Unnecessary, engine.check_observed(&c));
+}
+
+#[test]
+fn test_garbage_collection_wont_panic() {
+    let mut engine = Engine::new();
+    let v1 = Variable::new(1usize).watch();
+    let v2 = v1.map(|n| n + 1);
+    engine.get(&v2);
+    std::mem::drop(v1);
+    std::mem::drop(v2);
+    engine.stabilize();
+
+    // freed slots are reused by new nodes
+    let v3 = Anchor::constant(3usize).map(|n| n + 1);
+    assert_eq!(engine.get(&v3), 4);
+}
+
+#[test]
+fn test_split_simple() {
+    let mut engine = Engine::new();
+    let v = Variable::new((1usize, 2usize, 3usize));
+    let (a, b, c) = v.watch().split();
+    assert_eq!(engine.get(&a), 1);
+    assert_eq!(engine.get(&b), 2);
+    assert_eq!(engine.get(&c), 3);
+}
+
+/// Also run by engines with other configurations.
+pub(super) fn check_from_future_blocking_get(mut engine: Engine) {
+    let (send, receive) = oneshot();
+    let var = Variable::new(1usize);
+    let sum = (&Anchor::from_future(receive), &var.watch()).map(|a: &usize, b| a + b);
+
+    let handle = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        send(20);
+    });
+    assert_eq!(engine.get(&sum), 21);
+    handle.join().unwrap();
+
+    var.set(2);
+    assert_eq!(engine.get(&sum), 22);
+}
+
+#[test]
+fn test_from_future_blocking_get() {
+    check_from_future_blocking_get(Engine::new());
+}
+
+#[test]
+fn test_try_get_reports_errors() {
+    let mut engine = Engine::new();
+    let target: Variable<Option<Anchor<usize>>> = Variable::new(None);
+    let a = target
+        .watch()
+        .then(|target| target.clone().unwrap_or_else(|| Anchor::constant(1)));
+    assert_eq!(engine.try_get(&a), Ok(1));
+
+    // `a` now depends on itself
+    target.set(Some(a.clone()));
+    assert!(matches!(engine.try_get(&a), Err(Error::Cycle { .. })));
+
+    // the rest of the graph is still usable
+    let b = Anchor::constant(2usize).map(|n| n + 1);
+    assert_eq!(engine.try_get(&b), Ok(3));
+
+    // mounted before another engine becomes the current one
+    let broken = Anchor::constant(1usize).map(|_| -> usize { panic!("broken formula") });
+    let foreign = {
+        let _other = Engine::new();
+        Anchor::constant(3usize)
+    };
+    assert_eq!(
+        engine.try_get(&foreign),
+        Err(Error::ForeignAnchor { requested_by: None })
+    );
+
+    // a panicking formula poisons the engine
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| engine.get(&broken)));
+    assert!(result.is_err());
+    assert_eq!(engine.try_stabilize(), Err(Error::Poisoned));
+}
+
+#[test]
+fn test_try_methods_report_foreign_anchors() {
+    let mut engine = Engine::new();
+    let foreign = {
+        let _other = Engine::new();
+        Anchor::constant(3usize)
+    };
+    let err = Err(Error::ForeignAnchor { requested_by: None });
+    assert_eq!(engine.try_mark_unobserved(&foreign), err);
+    assert_eq!(engine.try_check_observed(&foreign).map(|_| ()), err);
+    assert_eq!(engine.try_last_changed(&foreign).map(|_| ()), err);
+    assert_eq!(block_on(engine.try_get_async(&foreign)).map(|_| ()), err);
+}
+
+#[test]
+fn test_cycle_reports_path() {
+    let mut engine = Engine::new();
+    let target: Variable<Option<Anchor<usize>>> = Variable::new(None);
+    let a = target
+        .watch()
+        .then(|target| target.clone().unwrap_or_else(|| Anchor::constant(1)));
+    let b = a.map(|n| n + 1);
+    let c = b.map(|n| n + 1);
+    assert_eq!(engine.try_get(&c), Ok(3));
+
+    target.set(Some(c.clone()));
+    let path = match engine.try_get(&c) {
+        Err(Error::Cycle { path }) => path,
+        other => panic!("expected a cycle, got {:?}", other),
+    };
+    // c depends on b, b on a, and a on c
+    assert_eq!(path.len(), 3);
+    assert!(path[0].ends_with("(map)"));
+    assert!(path[1].ends_with("(map)"));
+    assert!(path[2].ends_with("(then)"));
+    assert_ne!(path[0], path[1]);
+}
+
+#[test]
+fn test_engines_side_by_side() {
+    let mut first = Engine::new();
+    let first_var = Variable::new(1);
+    let mut second = Engine::new();
+    let second_var = Variable::new(10);
+
+    // `then` closures mount onto the engine stabilizing them, not the current one
+    let first_anchor = first.enter(|| {
+        first_var
+            .watch()
+            .then(|n| Anchor::constant(*n).map(|n| n + 1))
+    });
+    let second_anchor = second_var.watch().map(|n| n * 2);
+    assert_eq!(first.get(&first_anchor), 2);
+    assert_eq!(second.get(&second_anchor), 20);
+
+    {
+        let _guard = first.enter_guard();
+        let doubled = first_var.watch().map(|n| n * 2);
+        assert_eq!(first.get(&doubled), 2);
+    }
+    // the guard made `second` current again
+    let tripled = second_var.watch().map(|n| n * 3);
+    assert_eq!(second.get(&tripled), 30);
+
+    first_var.set(5);
+    second_var.set(50);
+    assert_eq!(first.get(&first_anchor), 6);
+    assert_eq!(second.get(&second_anchor), 100);
+}
+
+#[test]
+fn test_transaction() {
+    let mut engine = Engine::new();
+    let a = Variable::new(1);
+    let b = Variable::new(2);
+    let sum = (&a.watch(), &b.watch()).map(|a, b| a + b);
+    let recalculations = Arc::new(AtomicUsize::new(0));
+    let counted = {
+        let recalculations = Arc::clone(&recalculations);
+        sum.map(move |sum| {
+            recalculations.fetch_add(1, Ordering::SeqCst);
+            *sum
+        })
+    };
+    engine.mark_observed(&counted);
+    assert_eq!(engine.get(&counted), 3);
+
+    let res = engine.transaction(|tx| {
+        tx.set(&a, 10);
+        tx.set(&b, 20);
+        // nothing is applied before the transaction commits
+        assert_eq!(*a.get(), 1);
+        "done"
+    });
+    assert_eq!(res, "done");
+    assert_eq!(*a.get(), 10);
+    assert_eq!(engine.get(&counted), 30);
+    assert_eq!(recalculations.load(Ordering::SeqCst), 2);
+
+    engine.transaction(|tx| {
+        tx.set(&a, 100);
+        tx.rollback();
+        assert!(tx.is_empty());
+        tx.set(&b, 200);
+    });
+    assert_eq!(engine.get(&counted), 210);
+}
+
+#[test]
+fn test_clock() {
+    use std::time::Duration;
+
+    let mut engine = Engine::new();
+    let clock = engine.clock();
+    let start = clock.current();
+    let second = Duration::from_secs(1);
+
+    let deadline = clock.at(start + 3 * second);
+    let ticks = clock.every(2 * second);
+    let input = Variable::new(1);
+    let debounced = input.watch().debounce(&clock, second);
+    assert_eq!(engine.get(&clock.now()), start);
+    assert_eq!(engine.get(&deadline), BeforeOrAfter::Before);
+    assert_eq!(engine.get(&ticks), 0);
+    assert_eq!(engine.get(&debounced), 1);
+
+    input.set(2);
+    clock.advance_by(second / 2);
+    input.set(3);
+    assert_eq!(engine.get(&debounced), 1);
+    clock.advance_to(start + second);
+    assert_eq!(engine.get(&debounced), 1);
+    clock.advance_to(start + 3 * second / 2);
+    assert_eq!(engine.get(&debounced), 3);
+
+    clock.advance_to(start + 2 * second);
+    assert_eq!(engine.get(&deadline), BeforeOrAfter::Before);
+    assert_eq!(engine.get(&ticks), 1);
+    clock.advance_to(start + 7 * second);
+    assert_eq!(engine.get(&deadline), BeforeOrAfter::After);
+    assert_eq!(engine.get(&ticks), 3);
+
+    // the engine hands out the same clock every time
+    assert_eq!(engine.clock().current(), start + 7 * second);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_snapshot_restore() {
+    let mut engine = Engine::new();
+    let name = Variable::new("a".to_string());
+    let count = Variable::new(1);
+    engine.register_variable("name", &name);
+    engine.register_variable("count", &count);
+
+    let recalculations = Arc::new(AtomicUsize::new(0));
+    let label = {
+        let recalculations = Arc::clone(&recalculations);
+        name.watch().map(move |name| {
+            recalculations.fetch_add(1, Ordering::SeqCst);
+            name.to_uppercase()
+        })
+    };
+    let doubled = count.watch().map(|count| count * 2);
+    engine.mark_observed(&label);
+    engine.mark_observed(&doubled);
+    assert_eq!(engine.get(&label), "A");
+
+    let snapshot = engine.snapshot().unwrap();
+    let serialized = serde_json::to_string(&snapshot).unwrap();
+    assert_eq!(serialized, r#"{"count":1,"name":"a"}"#);
+
+    count.set(5);
+    assert_eq!(engine.get(&doubled), 10);
+
+    // only the changed variable is set, so `label` isn't recalculated
+    let snapshot = serde_json::from_str(&serialized).unwrap();
+    engine.restore(&snapshot).unwrap();
+    assert_eq!(engine.get(&doubled), 2);
+    assert_eq!(engine.get(&label), "A");
+    assert_eq!(recalculations.load(Ordering::SeqCst), 1);
+
+    // nothing is restored if any value doesn't fit its variable
+    let snapshot = serde_json::from_str(r#"{"count":3,"name":7}"#).unwrap();
+    assert!(matches!(
+        engine.restore(&snapshot),
+        Err(crate::core::SnapshotError::Serde { key, .. }) if key == "name"
+    ));
+    assert_eq!(*count.get(), 1);
+
+    let snapshot = serde_json::from_str(r#"{"missing":3}"#).unwrap();
+    assert!(matches!(
+        engine.restore(&snapshot),
+        Err(crate::core::SnapshotError::UnknownKey { key }) if key == "missing"
+    ));
+    assert!(engine.unregister_variable("count"));
+    assert_eq!(
+        engine.snapshot().unwrap().keys().collect::<Vec<_>>(),
+        ["name"]
+    );
+}
+
+#[test]
+fn test_freeze_and_pausable() {
+    let mut engine = Engine::new();
+    let input = Variable::new(1);
+    let recalculations = Arc::new(AtomicUsize::new(0));
+    let doubled = {
+        let recalculations = Arc::clone(&recalculations);
+        input.watch().map(move |value| {
+            recalculations.fetch_add(1, Ordering::SeqCst);
+            value * 2
+        })
+    };
+
+    let frozen = doubled.freeze();
+    engine.mark_observed(&frozen);
+    assert_eq!(engine.get(&frozen), 2);
+    assert_eq!(engine.check_observed(&doubled), ObservedState::Unnecessary);
+    input.set(2);
+    assert_eq!(engine.get(&frozen), 2);
+    assert_eq!(recalculations.load(Ordering::SeqCst), 1);
+    engine.mark_unobserved(&frozen);
+
+    let hidden = Variable::new(false);
+    let shown = doubled.pausable(&hidden.watch());
+    engine.mark_observed(&shown);
+    assert_eq!(engine.get(&shown), 4);
+    assert_eq!(engine.check_observed(&doubled), ObservedState::Necessary);
+    assert_eq!(recalculations.load(Ordering::SeqCst), 2);
+
+    hidden.set(true);
+    engine.stabilize();
+    assert_eq!(engine.check_observed(&doubled), ObservedState::Unnecessary);
+    input.set(3);
+    assert_eq!(engine.get(&shown), 4);
+    assert_eq!(recalculations.load(Ordering::SeqCst), 2);
+
+    hidden.set(false);
+    assert_eq!(engine.get(&shown), 6);
+    assert_eq!(engine.check_observed(&doubled), ObservedState::Necessary);
+    assert_eq!(recalculations.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn test_generation_queries() {
+    let mut engine = Engine::new();
+    let a = Variable::new(1);
+    let b = Variable::new(1);
+    let parity = a.watch().map(|a| a % 2);
+    let sum = (&parity, &b.watch()).map(|parity, b| parity + b);
+    engine.mark_observed(&sum);
+    assert_eq!(engine.last_changed(&sum), None);
+
+    assert_eq!(engine.get(&sum), 2);
+    let drawn = engine.current_generation();
+    assert_eq!(engine.last_changed(&sum), Some(drawn));
+    assert!(!engine.changed_since(&sum, drawn));
+
+    // the parity stays the same, so `sum` doesn't change
+    a.set(3);
+    engine.stabilize();
+    assert!(engine.current_generation() > drawn);
+    assert!(engine.changed_since(&a.watch(), drawn));
+    assert!(!engine.changed_since(&sum, drawn));
+
+    b.set(2);
+    engine.stabilize();
+    assert!(engine.changed_since(&sum, drawn));
+    assert_eq!(engine.last_changed(&sum), Some(engine.current_generation()));
+}
+
+#[test]
+fn test_then_memo_reuses_branches() {
+    let mut engine = Engine::new();
+    let tab = Variable::new(0);
+    let input = Variable::new(10);
+    let builds = Arc::new(AtomicUsize::new(0));
+    let recalculations = Arc::new(AtomicUsize::new(0));
+    let page = {
+        let input = input.watch();
+        let builds = Arc::clone(&builds);
+        let recalculations = Arc::clone(&recalculations);
+        tab.watch().then_memo(
+            |tab| *tab,
+            move |tab| {
+                let tab = *tab;
+                builds.fetch_add(1, Ordering::SeqCst);
+                let recalculations = Arc::clone(&recalculations);
+                input.map(move |input| {
+                    recalculations.fetch_add(1, Ordering::SeqCst);
+                    input + tab
+                })
+            },
+            2,
+        )
+    };
+    let counts = || {
+        (
+            builds.load(Ordering::SeqCst),
+            recalculations.load(Ordering::SeqCst),
+        )
+    };
+    engine.mark_observed(&page);
+    assert_eq!(engine.get(&page), 10);
+    tab.set(1);
+    assert_eq!(engine.get(&page), 11);
+
+    // switching back neither rebuilds nor recalculates the branch
+    tab.set(0);
+    assert_eq!(engine.get(&page), 10);
+    assert_eq!(counts(), (2, 2));
+
+    // a remembered branch whose input changed is recalculated once it's used again
+    input.set(20);
+    assert_eq!(engine.get(&page), 20);
+    tab.set(1);
+    assert_eq!(engine.get(&page), 21);
+    assert_eq!(counts(), (2, 4));
+
+    // the least recently used branch is evicted to make room for a third one
+    tab.set(2);
+    assert_eq!(engine.get(&page), 22);
+    tab.set(1);
+    assert_eq!(engine.get(&page), 21);
+    tab.set(0);
+    assert_eq!(engine.get(&page), 20);
+    assert_eq!(builds.load(Ordering::SeqCst), 4);
+}